@@ -0,0 +1,192 @@
+use std::io;
+use std::path::{Path, PathBuf};
+
+use clap::parser::ValueSource;
+use clap::ArgMatches;
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::{Args, Format, WeekStart, ZoneArg};
+
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("Unable to read the config file {}: {1}", .0.display())]
+    Read(PathBuf, io::Error),
+    #[error("Malformed config file {}: {1}", .0.display())]
+    Malformed(PathBuf, String),
+}
+
+/// The config file as written: every key is optional, and unknown keys are an error so a typo
+/// doesn't go unnoticed
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    city: Option<String>,
+    lat: Option<f64>,
+    long: Option<f64>,
+    tz: Option<String>,
+    format: Option<String>,
+    week_start: Option<WeekStart>,
+}
+
+/// Defaults for arguments that would otherwise have to be given every time, read from
+/// `$SUNTIME_CONFIG`, or else `suntime/config.toml` under `$XDG_CONFIG_HOME` (by default
+/// `~/.config`). For example:
+///
+/// ```toml
+/// city = "Toronto, Canada"
+/// tz = "America/Toronto"
+/// format = "csv"
+/// week_start = "sun"
+/// ```
+///
+/// Arguments given on the command line win over the config file, which in turn wins over the
+/// `SUNTIME_CITY`, `SUNTIME_LAT` and `SUNTIME_LONG` environment variables.
+#[derive(Debug, Default, PartialEq)]
+pub struct Config {
+    city: Option<String>,
+    lat: Option<f64>,
+    long: Option<f64>,
+    tz: Option<ZoneArg>,
+    format: Option<Format>,
+    week_start: Option<WeekStart>,
+}
+
+impl Config {
+    /// Reads the config file. A missing file at the default location is the same as an empty
+    /// one, but one named by `$SUNTIME_CONFIG` has to exist.
+    pub fn load() -> Result<Config, ConfigError> {
+        let (path, required) = match std::env::var_os("SUNTIME_CONFIG") {
+            Some(path) => (PathBuf::from(path), true),
+            None => match default_path() {
+                Some(path) => (path, false),
+                None => return Ok(Config::default()),
+            },
+        };
+        match std::fs::read_to_string(&path) {
+            Ok(text) => Config::parse(&path, &text),
+            Err(err) if !required && err.kind() == io::ErrorKind::NotFound => Ok(Config::default()),
+            Err(err) => Err(ConfigError::Read(path, err)),
+        }
+    }
+
+    fn parse(path: &Path, text: &str) -> Result<Config, ConfigError> {
+        let malformed = |err: &dyn std::fmt::Display| {
+            ConfigError::Malformed(path.to_path_buf(), err.to_string())
+        };
+        let file: ConfigFile = toml::from_str(text).map_err(|err| malformed(&err))?;
+        Ok(Config {
+            city: file.city,
+            lat: file.lat,
+            long: file.long,
+            tz: file
+                .tz
+                .map(|tz| tz.parse())
+                .transpose()
+                .map_err(|err| malformed(&err))?,
+            format: file
+                .format
+                .map(|format| format.parse())
+                .transpose()
+                .map_err(|err| malformed(&err))?,
+            week_start: file.week_start,
+        })
+    }
+
+    /// Fills in whatever `args` leaves unsaid. The location is taken from the config file only
+    /// if none was given at all, so a `--city` isn't combined with a configured latitude; the
+    /// time zone only if no other clock was asked for; and the format and week start only if
+    /// they weren't given, which `matches` tells apart from their defaults.
+    pub fn apply(self, args: &mut Args, matches: &ArgMatches) {
+        let no_location = args.coords.is_none()
+            && args.city.is_empty()
+            && args.lat.is_empty()
+            && args.long.is_empty();
+        if no_location {
+            args.city.extend(self.city);
+            args.lat.extend(self.lat);
+            args.long.extend(self.long);
+        }
+        if args.tz.is_none() && !args.utc && !args.local_time && !args.true_solar {
+            args.tz = self.tz;
+        }
+        let defaulted = |id: &str| matches.value_source(id) == Some(ValueSource::DefaultValue);
+        if let Some(format) = self.format.filter(|_| defaulted("format")) {
+            args.format = format;
+        }
+        if let Some(week_start) = self.week_start.filter(|_| defaulted("week_start")) {
+            args.week_start = week_start;
+        }
+    }
+}
+
+fn default_path() -> Option<PathBuf> {
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+    Some(config_home.join("suntime").join("config.toml"))
+}
+
+#[test]
+fn test_config() {
+    use clap::{CommandFactory, FromArgMatches};
+
+    let path = Path::new("config.toml");
+    let config = Config::parse(
+        path,
+        "city = \"Toronto, Canada\"\ntz = \"America/Toronto\"\nformat = \"csv\"\n",
+    )
+    .unwrap();
+    assert_eq!(config.tz, Some(ZoneArg::Named(chrono_tz::America::Toronto)));
+    assert_eq!(config.format, Some(Format::Csv));
+
+    let parse = |argv: &[&str]| {
+        let matches = Args::command().get_matches_from(argv);
+        let mut args = Args::from_arg_matches(&matches).unwrap();
+        Config::parse(
+            path,
+            "city = \"Toronto, Canada\"\ntz = \"+05:30\"\nformat = \"csv\"\nweek_start = \"sun\"\n",
+        )
+        .unwrap()
+        .apply(&mut args, &matches);
+        args
+    };
+
+    // The config file fills in what isn't given...
+    let args = parse(&["suntime"]);
+    assert_eq!(args.city, ["Toronto, Canada"]);
+    assert!(matches!(args.tz, Some(ZoneArg::Fixed(_))));
+    assert_eq!(args.format, Format::Csv);
+    assert_eq!(args.week_start, WeekStart::Sun);
+    // ...but the command line takes precedence, even when it asks for the default
+    let args = parse(&[
+        "suntime",
+        "--lat",
+        "51.5",
+        "--long",
+        "0",
+        "--utc",
+        "-f",
+        "human",
+        "--week-start",
+        "mon",
+    ]);
+    assert!(args.city.is_empty());
+    assert_eq!(args.tz, None);
+    assert_eq!(args.format, Format::Human);
+    assert_eq!(args.week_start, WeekStart::Mon);
+
+    assert_eq!(Config::parse(path, "").unwrap(), Config::default());
+    for bad in [
+        "city = ",
+        "citty = \"Paris\"",
+        "tz = \"Mars/Olympus\"",
+        "format = \"pdf\"",
+    ] {
+        assert!(
+            matches!(Config::parse(path, bad), Err(ConfigError::Malformed(..))),
+            "{bad}"
+        );
+    }
+}
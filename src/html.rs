@@ -0,0 +1,56 @@
+use std::fmt::Write;
+
+const STYLE: &str = "<style>
+  table.suntime { border-collapse: collapse; font-family: sans-serif; }
+  table.suntime th, table.suntime td { padding: 0.2em 0.8em; text-align: right; }
+  table.suntime tbody tr:nth-child(even) { background: #f4f4f4; }
+  table.suntime td.sunrise { color: #b8860b; }
+  table.suntime td.sunset { color: #c0392b; }
+</style>
+";
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Renders a table as an HTML fragment with a small default stylesheet. `columns` pairs each
+/// column's CSS class with its heading; every cell carries its column's class, so pages can style
+/// each kind of event separately.
+pub fn html_table(caption: &str, columns: &[(&str, &str)], rows: &[Vec<String>]) -> String {
+    let mut html = STYLE.to_string();
+    html.push_str("<table class=\"suntime\">\n");
+    writeln!(html, "  <caption>{}</caption>", escape(caption)).unwrap();
+    html.push_str("  <thead>\n    <tr>");
+    for (class, heading) in columns {
+        write!(html, "<th class=\"{class}\">{}</th>", escape(heading)).unwrap();
+    }
+    html.push_str("</tr>\n  </thead>\n  <tbody>\n");
+    for row in rows {
+        html.push_str("    <tr>");
+        for ((class, _), cell) in columns.iter().zip(row) {
+            write!(html, "<td class=\"{class}\">{}</td>", escape(cell)).unwrap();
+        }
+        html.push_str("</tr>\n");
+    }
+    html.push_str("  </tbody>\n</table>");
+    html
+}
+
+#[test]
+fn test_html_table() {
+    let html = html_table(
+        "Sun times for Q&A <town>",
+        &[("date", "Date"), ("sunrise", "Sunrise")],
+        &[vec!["2023-01-01".to_string(), "07:45:00".to_string()]],
+    );
+    assert!(html.contains("<caption>Sun times for Q&amp;A &lt;town&gt;</caption>"));
+    assert!(
+        html.contains("<tr><th class=\"date\">Date</th><th class=\"sunrise\">Sunrise</th></tr>")
+    );
+    assert!(html.contains(
+        "<tr><td class=\"date\">2023-01-01</td><td class=\"sunrise\">07:45:00</td></tr>"
+    ));
+}
@@ -0,0 +1,80 @@
+use std::fmt::Write;
+
+use chrono::{DateTime, Utc};
+
+/// A single moment to put on the calendar
+pub struct IcsEvent {
+    /// Identifies the event across exports, so re-importing a calendar updates its events
+    /// rather than duplicating them
+    pub uid: String,
+    pub summary: String,
+    pub start: DateTime<Utc>,
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+fn format_utc(dt: DateTime<Utc>) -> String {
+    dt.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+/// Renders events as an iCalendar (RFC 5545) VCALENDAR. Events are instants with no duration,
+/// marked transparent so they don't show as busy time. `stamp` is when the calendar was
+/// generated. With a `reminder`, each event also gets an alarm that pops up that many minutes
+/// before it.
+pub fn ics_calendar(events: &[IcsEvent], stamp: DateTime<Utc>, reminder: Option<u32>) -> String {
+    let mut ics = String::new();
+    ics.push_str("BEGIN:VCALENDAR\r\n");
+    ics.push_str("VERSION:2.0\r\n");
+    ics.push_str("PRODID:-//suntime//suntime//EN\r\n");
+    ics.push_str("CALSCALE:GREGORIAN\r\n");
+    for event in events {
+        ics.push_str("BEGIN:VEVENT\r\n");
+        write!(ics, "UID:{}\r\n", escape(&event.uid)).unwrap();
+        write!(ics, "DTSTAMP:{}\r\n", format_utc(stamp)).unwrap();
+        write!(ics, "DTSTART:{}\r\n", format_utc(event.start)).unwrap();
+        write!(ics, "SUMMARY:{}\r\n", escape(&event.summary)).unwrap();
+        ics.push_str("TRANSP:TRANSPARENT\r\n");
+        if let Some(minutes) = reminder {
+            ics.push_str("BEGIN:VALARM\r\n");
+            ics.push_str("ACTION:DISPLAY\r\n");
+            write!(ics, "DESCRIPTION:{}\r\n", escape(&event.summary)).unwrap();
+            write!(ics, "TRIGGER:-PT{minutes}M\r\n").unwrap();
+            ics.push_str("END:VALARM\r\n");
+        }
+        ics.push_str("END:VEVENT\r\n");
+    }
+    ics.push_str("END:VCALENDAR\r\n");
+    ics
+}
+
+#[test]
+fn test_ics_calendar() {
+    use chrono::TimeZone;
+
+    let stamp = Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap();
+    let start = Utc.with_ymd_and_hms(2023, 3, 20, 10, 58, 7).unwrap();
+    let events = [IcsEvent {
+        uid: "20230320-sunrise@suntime".to_string(),
+        summary: "Sunrise, early".to_string(),
+        start,
+    }];
+    let ics = ics_calendar(&events, stamp, None);
+    assert!(ics.starts_with("BEGIN:VCALENDAR\r\nVERSION:2.0\r\n"));
+    assert!(ics.ends_with("END:VEVENT\r\nEND:VCALENDAR\r\n"));
+    assert!(ics.contains("\r\nUID:20230320-sunrise@suntime\r\n"));
+    assert!(ics.contains("\r\nDTSTAMP:20230101T000000Z\r\n"));
+    assert!(ics.contains("\r\nDTSTART:20230320T105807Z\r\n"));
+    assert!(ics.contains("\r\nSUMMARY:Sunrise\\, early\r\n"));
+    assert!(!ics.replace("\r\n", "").contains('\n'));
+    assert!(!ics.contains("VALARM"));
+
+    let ics = ics_calendar(&events, stamp, Some(15));
+    let alarm = "TRANSP:TRANSPARENT\r\nBEGIN:VALARM\r\nACTION:DISPLAY\r\n\
+                 DESCRIPTION:Sunrise\\, early\r\nTRIGGER:-PT15M\r\nEND:VALARM\r\nEND:VEVENT\r\n";
+    assert!(ics.contains(alarm), "{ics}");
+}
@@ -1,9 +1,18 @@
-use std::collections::{HashMap, HashSet};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use chrono_tz::Tz;
 
 use flate2::read::GzDecoder;
-use serde::Deserialize;
-use suntime::Pos;
+use serde::{Deserialize, Serialize};
+use suntime::{Pos, PosError};
 use thiserror::Error;
+use tzf_rs::DefaultFinder;
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
 
 use crate::Args;
 
@@ -19,8 +28,18 @@ pub enum LocationError {
     ValueOutOfRange(f64, f64),
     #[error("Unknown city {0}")]
     UnknownCity(String),
+    #[error("Unable to load the city database: {0}")]
+    CityDb(String),
+    #[error("Malformed row in the city database, {0}")]
+    CsvParse(String),
     #[error("Unknown format {0}")]
     UnknownFormat(String),
+    #[error("Unknown time zone {0}; expected an offset like +05:30 or a name like Asia/Kolkata")]
+    UnknownTimeZone(String),
+    #[error("At most two locations can be compared, but {0} were given")]
+    TooManyLocations(usize),
+    #[error("Invalid date or time format {0:?}; expected a strftime string like \"%I:%M %p\"")]
+    BadTimeFormat(String),
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -33,6 +52,7 @@ struct LocationRow {
     iso2: String,
     iso3: String,
     admin_name: String,
+    population: Option<f64>,
 }
 
 impl LocationRow {
@@ -41,89 +61,336 @@ impl LocationRow {
     }
 }
 
-fn load_loc_data() -> Vec<LocationRow> {
-    let raw = &include_bytes!("worldcities.csv.gz")[..];
-    let decoded = GzDecoder::new(raw);
-    let mut rv = vec![];
-    for row in csv::Reader::from_reader(decoded).deserialize() {
-        let row: LocationRow = row.unwrap();
-        rv.push(row);
+/// Reads city data from `path`, gzipped or not, or the embedded data if there's no path
+fn load_loc_data(path: Option<&Path>) -> Result<Vec<LocationRow>, LocationError> {
+    let raw: Cow<[u8]> = match path {
+        Some(path) => Cow::Owned(
+            std::fs::read(path)
+                .map_err(|err| LocationError::CityDb(format!("{}: {err}", path.display())))?,
+        ),
+        None => Cow::Borrowed(&include_bytes!("worldcities.csv.gz")[..]),
+    };
+    let reader: Box<dyn Read> = if raw.starts_with(&[0x1f, 0x8b]) {
+        Box::new(GzDecoder::new(&raw[..]))
+    } else {
+        Box::new(&raw[..])
+    };
+    let rows = csv::Reader::from_reader(reader)
+        .deserialize()
+        .map(|row| {
+            row.map_err(|err| {
+                let line = err
+                    .position()
+                    .map_or_else(|| "?".to_string(), |pos| pos.line().to_string());
+                LocationError::CsvParse(format!("line {line}: {err}"))
+            })
+        })
+        .collect();
+    rows
+}
+
+/// All rows of the city data, loaded on first use and shared after. `path` is only consulted on
+/// the first call.
+fn locations(path: Option<&Path>) -> Result<&'static [LocationRow], LocationError> {
+    static LOCATIONS: OnceLock<Vec<LocationRow>> = OnceLock::new();
+    if let Some(rows) = LOCATIONS.get() {
+        return Ok(rows);
     }
-    rv
+    let rows = load_loc_data(path)?;
+    Ok(LOCATIONS.get_or_init(|| rows))
 }
 
-fn check_countries(name: &str, row: &LocationRow) -> bool {
-    check_country(name, &row.country)
-    || check_country(name, &row.iso2)
-    || check_country(name, &row.iso3)
+/// Lowercases `text` and strips its accents, so "São Paulo" compares equal to "sao paulo". Names
+/// are decomposed (NFKD) and the combining marks dropped, which also turns ligatures like "ﬁ"
+/// into plain letters.
+fn fold(text: &str) -> String {
+    text.to_lowercase()
+        .nfkd()
+        .filter(|c| !is_combining_mark(*c))
+        .collect()
+}
+
+/// FNV-1a hash of `text` after `fold`, without allocating for ASCII text
+fn fold_hash(text: &str) -> u64 {
+    let hash = |bytes: &mut dyn Iterator<Item = u8>| {
+        bytes.fold(0xcbf2_9ce4_8422_2325, |hash, b| {
+            (hash ^ u64::from(b)).wrapping_mul(0x100_0000_01b3)
+        })
+    };
+    if text.is_ascii() {
+        hash(&mut text.bytes().map(|b| b.to_ascii_lowercase()))
+    } else {
+        hash(&mut fold(text).bytes())
+    }
+}
+
+/// The `fold_hash` of every row's `city` and `city_ascii` in `locations(path)`, paired with the
+/// row's index and sorted, so a lookup only has to check the rows whose name could start the
+/// query. Built on first use.
+fn city_index(path: Option<&Path>) -> Result<&'static [(u64, usize)], LocationError> {
+    static INDEX: OnceLock<Vec<(u64, usize)>> = OnceLock::new();
+    if let Some(index) = INDEX.get() {
+        return Ok(index);
+    }
+    let rows = locations(path)?;
+    Ok(INDEX.get_or_init(|| {
+        let mut index = Vec::with_capacity(rows.len() * 2);
+        for (i, row) in rows.iter().enumerate() {
+            let city = fold_hash(&row.city);
+            let city_ascii = fold_hash(&row.city_ascii);
+            index.push((city, i));
+            if city_ascii != city {
+                index.push((city_ascii, i));
+            }
+        }
+        index.sort_unstable();
+        index
+    }))
+}
+
+/// Finds the cities matching `name` (which must already be passed through `fold`), and how each
+/// matched. With a `country` (also folded), only cities in that country are considered.
+fn match_to_city(
+    name: &str,
+    country: Option<&str>,
+    db: Option<&Path>,
+) -> Result<Vec<(&'static LocationRow, MatchReason)>, LocationError> {
+    let rows = locations(db)?;
+    let index = city_index(db)?;
+    // A city name is followed by the end of the query, a comma or a space
+    let mut candidates: Vec<usize> = name
+        .char_indices()
+        .filter(|(_, c)| *c == ',' || c.is_whitespace())
+        .map(|(i, _)| i)
+        .chain([name.len()])
+        .flat_map(|end| {
+            let hash = fold_hash(&name[..end]);
+            let start = index.partition_point(|&(h, _)| h < hash);
+            index[start..]
+                .iter()
+                .take_while(move |&&(h, _)| h == hash)
+                .map(|&(_, i)| i)
+        })
+        .collect();
+    candidates.sort_unstable();
+    candidates.dedup();
+    // Different names can share a hash, so `check_city` still has the final say
+    let matches = candidates
+        .into_iter()
+        .map(|i| &rows[i])
+        .filter(|row| country.is_none_or(|country| check_countries(country, row).is_some()))
+        .filter_map(|row| {
+            [("city", &row.city), ("city_ascii", &row.city_ascii)]
+                .into_iter()
+                .find_map(|(field, city)| {
+                    check_city(name, city, row).map(|qualifier| MatchReason { field, qualifier })
+                })
+                .map(|reason| (row, reason))
+        })
+        .collect();
+    Ok(matches)
+}
+
+/// How the part of a query after the city name matched a row
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Qualifier {
+    /// Nothing followed the city name
+    CityOnly,
+    /// A country followed the city, matching the given column
+    Country(&'static str),
+    /// A state (`admin_name`) followed the city
+    State,
+    /// A state and then a country followed the city, the country matching the given column
+    StateCountry(&'static str),
+}
+
+/// Which column of a row a query matched, and how
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct MatchReason {
+    field: &'static str,
+    qualifier: Qualifier,
+}
+
+impl std::fmt::Display for MatchReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "matched {}", self.field)?;
+        match self.qualifier {
+            Qualifier::CityOnly => Ok(()),
+            Qualifier::Country(column) => write!(f, ", then {column}"),
+            Qualifier::State => write!(f, ", then admin_name"),
+            Qualifier::StateCountry(column) => write!(f, ", then admin_name, then {column}"),
+        }
+    }
+}
+
+fn check_countries(name: &str, row: &LocationRow) -> Option<&'static str> {
+    [
+        ("country", &row.country),
+        ("iso2", &row.iso2),
+        ("iso3", &row.iso3),
+    ]
+    .into_iter()
+    .find(|(_, country)| check_country(name, country))
+    .map(|(column, _)| column)
 }
 
 fn check_country(name: &str, country: &str) -> bool {
-    let country = country.to_lowercase();
+    let country = fold(country);
     name == country
 }
 
-fn check_state(name: &str, state: &str, row: &LocationRow) -> bool {
-    let state = state.to_lowercase();
+fn check_state(name: &str, state: &str, row: &LocationRow) -> Option<Qualifier> {
+    let state = fold(state);
     if let Some(rest) = name.strip_prefix(&state) {
         if rest.trim().is_empty() {
-            true
+            Some(Qualifier::State)
         } else if rest.starts_with(',') || rest.starts_with(' ') {
             let rest = rest.trim_start_matches(',').trim();
-            check_countries(rest, row)
+            check_countries(rest, row).map(Qualifier::StateCountry)
         } else {
-            false
+            None
         }
     } else {
-        false
+        None
     }
 }
 
-fn check_city(name: &str, city: &str, row: &LocationRow) -> bool {
-    let city_low = city.to_lowercase();
+fn check_city(name: &str, city: &str, row: &LocationRow) -> Option<Qualifier> {
+    let city_low = fold(city);
 
     if let Some(rest) = name.strip_prefix(&city_low) {
         if rest.trim().is_empty() {
-            true
+            Some(Qualifier::CityOnly)
         } else if rest.starts_with(',') || rest.starts_with(' ') {
             let rest = rest.trim_start_matches(',').trim();
             check_countries(rest, row)
-                || (!row.admin_name.is_empty() && check_country(rest, &row.admin_name))
-                || check_state(rest, &row.admin_name, row)
+                .map(Qualifier::Country)
+                .or_else(|| {
+                    (!row.admin_name.is_empty() && check_country(rest, &row.admin_name))
+                        .then_some(Qualifier::State)
+                })
+                .or_else(|| check_state(rest, &row.admin_name, row))
         } else {
-            false
+            None
         }
     } else {
-        false
+        None
     }
 }
 
-fn match_to_city<'a>(name: &str, locations: &'a [LocationRow]) -> Vec<&'a LocationRow> {
-    locations
-        .iter()
-        .filter(|row| check_city(name, &row.city, row) || check_city(name, &row.city_ascii, row))
-        .collect()
+fn explain_match(query: &str, row: &LocationRow, reason: MatchReason) {
+    eprintln!("'{query}' {reason}:");
+    eprintln!(
+        "  city: {}, city_ascii: {}, admin_name: {}, country: {}, iso2: {}, iso3: {}, lat: {}, lng: {}",
+        row.city, row.city_ascii, row.admin_name, row.country, row.iso2, row.iso3, row.lat, row.lng
+    );
+}
+
+/// What to do when a city name matches more than one city
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ambiguous {
+    /// Use the most populous match
+    Largest,
+    /// Fail, listing the matches
+    Error,
+}
+
+/// Recognises a city name that's really a coordinate pair (like "45.5,-122.6", copied from a
+/// map), returning `None` for anything that should be looked up as a city, such as
+/// "Paris, France"
+fn city_as_coords(city: &str) -> Option<Result<Pos, LocationError>> {
+    match city.parse::<Pos>() {
+        Ok(pos) => Some(Ok(pos)),
+        Err(PosError::OutOfRange(lat, long)) => {
+            Some(Err(LocationError::ValueOutOfRange(lat, long)))
+        }
+        Err(PosError::Malformed(_)) => None,
+    }
+}
+
+/// Finds the cities matching `city`, in the city database and country given in `args`
+fn matches_for_args(
+    city: &str,
+    args: &Args,
+) -> Result<Vec<(&'static LocationRow, MatchReason)>, LocationError> {
+    let db = args
+        .city_db
+        .clone()
+        .or_else(|| std::env::var_os("SUNTIME_CITY_DB").map(PathBuf::from));
+    let country = args.country.as_deref().map(fold);
+    match_to_city(&fold(city), country.as_deref(), db.as_deref())
+}
+
+/// A city matching a query, as listed by `--list-cities`
+#[derive(Debug, Serialize)]
+pub struct CityCandidate {
+    city: &'static str,
+    admin_name: &'static str,
+    country: &'static str,
+    iso2: &'static str,
+    lat: f64,
+    lng: f64,
 }
 
-fn city_to_pos(city: &str) -> Result<Pos, LocationError> {
-    let locations = load_loc_data();
-    let city_low = city.to_lowercase();
-    let city_results = match_to_city(&city_low, &locations);
+/// Every city `query` could mean, without choosing between them as `--city` would
+pub fn list_cities(query: &str, args: &Args) -> Result<Vec<CityCandidate>, LocationError> {
+    let candidates = matches_for_args(query, args)?
+        .into_iter()
+        .map(|(row, _)| CityCandidate {
+            city: &row.city,
+            admin_name: &row.admin_name,
+            country: &row.country,
+            iso2: &row.iso2,
+            lat: row.lat,
+            lng: row.lng,
+        })
+        .collect();
+    Ok(candidates)
+}
+
+fn city_to_pos(city: &str, args: &Args) -> Result<Pos, LocationError> {
+    if let Some(pos) = city_as_coords(city) {
+        return pos;
+    }
+    let city_results = matches_for_args(city, args)?;
+    if args.explain_match {
+        for (row, reason) in &city_results {
+            explain_match(city, row, *reason);
+        }
+    }
 
     let suggestions = if city_results.len() == 1 {
-        return Ok(city_results[0].to_pos());
+        return Ok(city_results[0].0.to_pos());
     } else {
         city_results
     };
 
+    if args.ambiguous == Ambiguous::Largest {
+        let largest = suggestions.iter().max_by(|(a, _), (b, _)| {
+            a.population
+                .unwrap_or(0.)
+                .total_cmp(&b.population.unwrap_or(0.))
+        });
+        if let Some((row, _)) = largest {
+            eprintln!(
+                "Using {}, {}, {}, the largest of {} cities matching '{city}'",
+                row.city,
+                row.admin_name,
+                row.iso2,
+                suggestions.len()
+            );
+            return Ok(row.to_pos());
+        }
+    }
+
     if !suggestions.is_empty() {
         eprintln!("Multiple cities matched '{city}'. Did you mean:");
 
         let mut country_count: HashMap<&str, u32> = HashMap::new();
-        for suggestion in &suggestions {
+        for (suggestion, _) in &suggestions {
             *country_count.entry(&suggestion.iso2).or_default() += 1;
         }
-        for suggestion in &suggestions {
+        for (suggestion, _) in &suggestions {
             if country_count[&suggestion.iso2.as_str()] > 1 {
                 eprintln!(
                     "  * {}, {}, {}",
@@ -137,24 +404,57 @@ fn city_to_pos(city: &str) -> Result<Pos, LocationError> {
     Err(LocationError::UnknownCity(city.to_owned()))
 }
 
-pub fn validate_location(args: &Args) -> Result<Pos, LocationError> {
-    let (lat, long, city) = if args.lat.is_none() && args.long.is_none() && args.city.is_none() {
+/// The locations to show times for: usually one, or two to compare. Two need to be given the same
+/// way, as two cities or two lat/long pairs.
+pub fn validate_location(args: &Args) -> Result<Vec<Pos>, LocationError> {
+    if let Some(pos) = args.coords {
+        return Ok(vec![pos]);
+    }
+    if args.lat.is_empty() && args.long.is_empty() && args.city.is_empty() {
         // Get values from env vars
-        (
-            env_arg_to_f64("SUNTIME_LAT"),
-            env_arg_to_f64("SUNTIME_LONG"),
-            std::env::var("SUNTIME_CITY").ok(),
-        )
+        let lat = env_arg_to_f64("SUNTIME_LAT");
+        let long = env_arg_to_f64("SUNTIME_LONG");
+        let city = std::env::var("SUNTIME_CITY").ok();
+        return one_location(lat, long, city.as_deref(), args).map(|pos| vec![pos]);
+    }
+    let (lats, longs, cities) = (&args.lat, &args.long, &args.city);
+    if lats.len() <= 1 && longs.len() <= 1 && cities.len() <= 1 {
+        let city = cities.first().map(String::as_str);
+        return one_location(lats.first().copied(), longs.first().copied(), city, args)
+            .map(|pos| vec![pos]);
+    }
+    if lats.len() != longs.len() {
+        return Err(LocationError::BothOrNeitherLatLong);
+    }
+    if !lats.is_empty() && !cities.is_empty() {
+        return Err(LocationError::AmbiguousLocation);
+    }
+    if lats.len() + cities.len() > 2 {
+        return Err(LocationError::TooManyLocations(lats.len() + cities.len()));
+    }
+    if cities.is_empty() {
+        lats.iter()
+            .zip(longs)
+            .map(|(&lat, &long)| one_location(Some(lat), Some(long), None, args))
+            .collect()
     } else {
-        (args.lat, args.long, args.city.clone())
-    };
-    match (lat, long, &city) {
+        cities.iter().map(|city| city_to_pos(city, args)).collect()
+    }
+}
+
+fn one_location(
+    lat: Option<f64>,
+    long: Option<f64>,
+    city: Option<&str>,
+    args: &Args,
+) -> Result<Pos, LocationError> {
+    match (lat, long, city) {
         (None, None, None) => Err(LocationError::NoLocation),
-        (None, None, Some(city)) => city_to_pos(city),
+        (None, None, Some(city)) => city_to_pos(city, args),
         (None, Some(_), None) => Err(LocationError::BothOrNeitherLatLong),
-        (None, Some(_), Some(city)) => city_to_pos(city),
+        (None, Some(_), Some(city)) => city_to_pos(city, args),
         (Some(_), None, None) => Err(LocationError::BothOrNeitherLatLong),
-        (Some(_), None, Some(city)) => city_to_pos(city),
+        (Some(_), None, Some(city)) => city_to_pos(city, args),
         (Some(lat), Some(long), None)
             if (-90. ..=90.).contains(&lat) && (-180. ..=180.).contains(&long) =>
         {
@@ -177,3 +477,131 @@ fn env_arg_to_f64(name: &str) -> Option<f64> {
             .ok()
     })
 }
+
+/// Looks up the IANA time zone containing `pos` from bundled zone boundaries. Returns `None`
+/// when the lookup finds nothing, or a zone `chrono-tz` doesn't know.
+pub fn zone_at(pos: Pos) -> Option<Tz> {
+    static FINDER: OnceLock<DefaultFinder> = OnceLock::new();
+    let finder = FINDER.get_or_init(DefaultFinder::new);
+    finder.get_tz_name(pos.long(), pos.lat()).parse().ok()
+}
+
+#[test]
+fn test_city_as_coords() {
+    assert!(matches!(city_as_coords("45.5,-122.6"), Some(Ok(_))));
+    assert!(matches!(city_as_coords(" 45.5, -122.6 "), Some(Ok(_))));
+    assert!(matches!(
+        city_as_coords("-122.6,45.5"),
+        Some(Err(LocationError::ValueOutOfRange(..)))
+    ));
+    assert!(city_as_coords("Paris, France").is_none());
+    assert!(city_as_coords("Springfield, Illinois, US").is_none());
+}
+
+#[test]
+fn test_match_to_city_in_country() {
+    let states = |country| {
+        let matches = match_to_city("portland", country, None).unwrap();
+        matches
+            .iter()
+            .map(|(row, _)| (row.iso2.as_str(), row.admin_name.as_str()))
+            .collect::<Vec<_>>()
+    };
+    let everywhere = states(None);
+    assert!(everywhere.contains(&("AU", "Victoria")), "{everywhere:?}");
+    assert!(everywhere.contains(&("US", "Maine")), "{everywhere:?}");
+    // By ISO2, ISO3 or name, in any case
+    assert_eq!(states(Some("au")), [("AU", "Victoria")]);
+    assert_eq!(states(Some("aus")), [("AU", "Victoria")]);
+    assert_eq!(states(Some("australia")), [("AU", "Victoria")]);
+    // Several Portlands in the US are still ambiguous, but they're all American
+    let us = states(Some("us"));
+    assert!(
+        us.len() > 1 && us.iter().all(|(iso2, _)| *iso2 == "US"),
+        "{us:?}"
+    );
+    assert!(states(Some("fr")).is_empty());
+}
+
+#[test]
+fn test_accents() {
+    assert_eq!(fold("São Paulo"), "sao paulo");
+    assert_eq!(fold("ZÜRICH"), "zurich");
+    let first = |query| {
+        let matches = match_to_city(&fold(query), None, None).unwrap();
+        matches
+            .first()
+            .map(|(row, _)| (row.city.as_str(), row.admin_name.as_str()))
+    };
+    assert_eq!(first("sao paulo"), Some(("São Paulo", "São Paulo")));
+    assert_eq!(first("Zürich"), Some(("Zürich", "Zürich")));
+    // The state has no ASCII column, so this only matches with accents stripped
+    assert_eq!(
+        first("Campinas, Sao Paulo"),
+        Some(("Campinas", "São Paulo"))
+    );
+    assert_eq!(
+        first("campinas, são paulo, br"),
+        Some(("Campinas", "São Paulo"))
+    );
+}
+
+#[test]
+fn test_list_cities() {
+    use clap::Parser;
+
+    let args = Args::parse_from(["suntime", "--list-cities", "Portland", "--country", "AU"]);
+    let cities = list_cities("Portland", &args).unwrap();
+    let json = serde_json::to_value(&cities).unwrap();
+    assert_eq!(json[0]["admin_name"], "Victoria");
+    assert_eq!(json[0]["iso2"], "AU");
+    assert!(json[0]["lat"].as_f64().unwrap() < 0.);
+    assert_eq!(json.as_array().unwrap().len(), 1);
+    assert!(list_cities("Nowhereville", &args).unwrap().is_empty());
+}
+
+#[test]
+fn test_load_loc_data_malformed_row() {
+    let path = std::env::temp_dir().join(format!("suntime-cities-{}.csv", std::process::id()));
+    std::fs::write(
+        &path,
+        "city,city_ascii,lat,lng,country,iso2,iso3,admin_name,population\n\
+         Springfield,Springfield,39.8,-89.6,United States,US,USA,Illinois,114394\n\
+         Nowhere,Nowhere,north,-89.6,United States,US,USA,Illinois,\n",
+    )
+    .unwrap();
+    let result = load_loc_data(Some(&path));
+    std::fs::remove_file(&path).unwrap();
+    match result {
+        Err(LocationError::CsvParse(message)) => {
+            assert!(message.starts_with("line 3:"), "{message}")
+        }
+        other => panic!("Expected a parse error, got {other:?}"),
+    }
+}
+
+#[test]
+fn test_city_index() {
+    // The index has to find everything a scan of every row would
+    for query in [
+        "new york",
+        "portland or",
+        "campinas, são paulo, br",
+        "st. john's",
+        "xyzzy",
+    ] {
+        let query = fold(query);
+        let scanned: Vec<_> = locations(None)
+            .unwrap()
+            .iter()
+            .filter_map(|row| check_city(&query, &row.city, row).map(|_| row.city.as_str()))
+            .collect();
+        let indexed: Vec<_> = match_to_city(&query, None, None)
+            .unwrap()
+            .into_iter()
+            .filter(|(_, reason)| reason.field == "city")
+            .map(|(row, _)| row.city.as_str())
+            .collect();
+        assert_eq!(indexed, scanned, "{query}");
+    }
+}
@@ -1,14 +1,39 @@
-use std::{error::Error, str::FromStr};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    error::Error,
+    fs::File,
+    io::{self, BufWriter, IsTerminal, Write},
+    ops::RangeInclusive,
+    path::PathBuf,
+    str::FromStr,
+};
 
+use chrono::format::{Item, StrftimeItems};
 use chrono::{prelude::*, Duration};
+use chrono_tz::Tz;
 
-use clap::Parser;
-use location::{validate_location, LocationError};
-use plot::plot_times;
-use serde::{Serialize, Serializer};
-use suntime::Pos;
+use clap::{CommandFactory, FromArgMatches, Parser};
+use config::Config;
+use html::html_table;
+use ics::{ics_calendar, IcsEvent};
+use location::{list_cities, validate_location, zone_at, Ambiguous, LocationError};
+use markdown::markdown_table;
+use plot::{
+    plot_daylight_map, plot_durations, plot_durations_svg, plot_times, plot_times_multi,
+    plot_times_svg, sparkline, Glyphs, PlotStyle,
+};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use suntime::{
+    equation_of_time, meridian_sunrise_sunset, moon_phase, seasons, solar_declination,
+    AccuracyWarning, MoonPhase, PhaseName, Pos, Twilight,
+};
+use thiserror::Error;
 
+mod config;
+mod html;
+mod ics;
 mod location;
+mod markdown;
 mod plot;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -17,6 +42,10 @@ enum Format {
     Csv,
     Json,
     Plot,
+    Html,
+    Ics,
+    Markdown,
+    Svg,
 }
 
 impl FromStr for Format {
@@ -28,6 +57,10 @@ impl FromStr for Format {
             "csv" => Ok(Format::Csv),
             "json" => Ok(Format::Json),
             "plot" => Ok(Format::Plot),
+            "html" => Ok(Format::Html),
+            "ics" => Ok(Format::Ics),
+            "markdown" => Ok(Format::Markdown),
+            "svg" => Ok(Format::Svg),
             _ => Err(LocationError::UnknownFormat(s.to_string())),
         }
     }
@@ -37,31 +70,307 @@ impl FromStr for Format {
 /// Sunrise/set table generator
 ///
 /// Uses location data from https://simplemaps.com/data/world-cities
+///
+/// Defaults for city, lat, long, tz, format and week_start can be set in
+/// ~/.config/suntime/config.toml, or the file named by SUNTIME_CONFIG. Arguments given here take
+/// precedence over the config file, which takes precedence over the SUNTIME_CITY, SUNTIME_LAT
+/// and SUNTIME_LONG environment variables.
 pub struct Args {
+    /// Location as a single "lat,long" pair or geo: URI; incompatible with --city, --lat and --long
+    #[arg(
+        value_name = "LAT,LONG",
+        allow_hyphen_values = true,
+        conflicts_with_all = ["city", "lat", "long"]
+    )]
+    coords: Option<Pos>,
     #[arg(short, long)]
-    /// Location name in the form "City", "City, Country (Code)", or "City, State, Country (Code)"
-    city: Option<String>,
+    /// Location name in the form "City", "City, Country (Code)", or "City, State, Country (Code)".
+    /// Give it twice to compare two cities day by day
+    city: Vec<String>,
     #[arg(long)]
-    /// Latitude; requires longitude as well, and is incompatible with --city
-    lat: Option<f64>,
+    /// Latitude; requires longitude as well, and is incompatible with --city. Give both twice to
+    /// compare two locations
+    lat: Vec<f64>,
     #[arg(long)]
     /// Longitude; requires latitude as well, and is incompatible with --city
-    long: Option<f64>,
+    long: Vec<f64>,
     /// Plot width. Default: 120
     #[arg(long)]
     width: Option<usize>,
     /// Plot height. Default: 10
     #[arg(long)]
     height: Option<usize>,
+    /// Print day length across the range as a single row of block characters instead
+    #[arg(long)]
+    sparkline: bool,
 
     #[command(subcommand)]
     mode: Option<Mode>,
-    /// Output format: human, csv, json or plot
+    /// Output format: human, csv, json, plot, html, markdown, ics (an iCalendar file of
+    /// sunrise and sunset events) or svg (the plot as an image)
     #[arg(short, long, default_value = "human")]
     format: Format,
+    /// Print just the CSV column names (date,sunrise_s,noon_s,sunset_s,day_length_s, then any
+    /// added by --twilight or --moon), which also head CSV output, and exit.
+    /// Times are seconds since local midnight; day length is in seconds. Sunrise and sunset are
+    /// empty on days the sun doesn't cross the horizon
+    #[arg(long)]
+    header_only: bool,
+    /// Warn on stderr when the location or any year in the range is one where results may be off
+    /// by over a minute. There's no more accurate calculation to switch to; this only flags
+    /// results to take with a pinch of salt
+    #[arg(long)]
+    warn_accuracy: bool,
+    /// Show when the sun is this many degrees below the horizon each morning and evening
+    /// (e.g. 18 for astronomical dawn, or a tradition's dawn prayer angle), instead of sunrise
+    /// and sunset
+    #[arg(long, value_name = "DEG")]
+    depression: Option<f64>,
+    /// Show times in apparent solar time, as read from a sundial at the location, rather than
+    /// clock time. Solar noon is then always 12:00; the default clock time differs from it by
+    /// the location's offset from its time zone meridian plus the equation of time
+    #[arg(long, conflicts_with_all = ["tz", "utc"])]
+    true_solar: bool,
+    /// Fail if any event falls on a different calendar day than the one it was computed for,
+    /// rather than showing it as-is
+    #[arg(long)]
+    strict: bool,
+    /// Explain on stderr which fields of which rows matched the --city query
+    #[arg(long)]
+    explain_match: bool,
+    /// In human format, show each day as a multi-line card with solar altitude and civil
+    /// twilight alongside the usual times
+    #[arg(long)]
+    almanac: bool,
+    /// With the month command, lay the days out as a Monday-first calendar grid of sunrises and
+    /// sunsets
+    #[arg(long)]
+    calendar: bool,
+    /// In JSON output, also give each event as integer milliseconds since the Unix epoch
+    #[arg(long)]
+    epoch_ms: bool,
+    /// Show each day's times in every one of these comma-separated IANA time zones (e.g.
+    /// "America/New_York,Europe/London"), in human, csv or json format
+    #[arg(long, value_delimiter = ',', value_name = "ZONES")]
+    timezones: Vec<Tz>,
+    /// Show times in this time zone instead of the location's: a fixed offset like "+05:30",
+    /// or an IANA name like "Asia/Kolkata"
+    #[arg(long, value_name = "ZONE", conflicts_with = "utc")]
+    tz: Option<ZoneArg>,
+    /// Show times in UTC; shorthand for --tz +00:00
+    #[arg(long)]
+    utc: bool,
+    /// Show times in this machine's time zone rather than the location's
+    #[arg(long, conflicts_with_all = ["tz", "utc", "true_solar"])]
+    local_time: bool,
+    /// What to do when --city matches several cities: use the most populous, or fail listing
+    /// them
+    #[arg(long, value_enum, default_value = "largest")]
+    ambiguous: Ambiguous,
+    /// CSV file of cities to use instead of the built-in list; may also be set with
+    /// SUNTIME_CITY_DB. It may be gzipped, and must have the same columns as the built-in data:
+    /// city, city_ascii, lat, lng, country, iso2, iso3, admin_name and (optionally filled)
+    /// population
+    #[arg(long, value_name = "PATH")]
+    city_db: Option<PathBuf>,
+    /// Draw plots with plain ASCII characters instead of braille
+    #[arg(long)]
+    ascii: bool,
+    /// What --format plot draws across a range of days
+    #[arg(long, value_enum, default_value = "sun-times")]
+    series: PlotSeries,
+    /// Add horizontal gridlines to plots, labelled with their values
+    #[arg(long)]
+    grid: bool,
+    /// Print the longest and shortest days, earliest sunrise and latest sunset across the range
+    /// instead of each day. Supports human and json output
+    #[arg(long)]
+    summary: bool,
+    /// Keep showing today's times and a countdown to the next sunrise or sunset, refreshed every
+    /// SECS seconds until interrupted. With --format json, prints one object per line each time
+    #[arg(long, value_name = "SECS")]
+    watch: Option<u64>,
+    /// Label events with words instead of emoji in human output. This is the default when TERM
+    /// is "dumb" or the locale isn't UTF-8
+    #[arg(long)]
+    no_emoji: bool,
+    /// In human format, end each line with the day's length as "Day: HH:MM:SS"
+    #[arg(long)]
+    show_day_length: bool,
+    /// When to colour human output and plots: always, never, or only when writing to a terminal
+    #[arg(long, value_enum, default_value = "auto")]
+    color: ColorChoice,
+    /// Write output to this file instead of stdout
+    #[arg(long, value_name = "PATH")]
+    output: Option<PathBuf>,
+    /// Add the moon's phase, how much of it is lit, and when it rises and sets to each day in
+    /// human, csv and json output
+    #[arg(long)]
+    moon: bool,
+    /// Print whether the sun is up right now, as true or false, and exit with status 0 if it is
+    /// or 1 if it isn't
+    #[arg(long)]
+    daytime: bool,
+    /// Add the ratio of a shadow's length to the height of what casts it at solar noon to each
+    /// day in human output
+    #[arg(long)]
+    shadow: bool,
+    /// Label yesterday, today and tomorrow by name instead of by date in human output
+    #[arg(long)]
+    relative: bool,
+    /// How to write dates in human and csv output, as a strftime string (e.g. "%b %d")
+    #[arg(long, value_name = "FORMAT", default_value = DEFAULT_DATE_FORMAT)]
+    date_format: TimeFormat,
+    /// How to write times in human output, as a strftime string (e.g. "%I:%M %p" for a 12-hour
+    /// clock)
+    #[arg(long, value_name = "FORMAT", default_value = DEFAULT_TIME_FORMAT)]
+    time_format: TimeFormat,
+    /// Only match --city against cities in this country, given by name or ISO code (e.g. "US"
+    /// or "USA"). Several matches in the country are still handled as --ambiguous says
+    #[arg(long)]
+    country: Option<String>,
+    /// Print every city QUERY could mean as JSON (city, admin_name, country, iso2, lat and
+    /// lng) instead of sun times; an empty list if there are none
+    #[arg(long, value_name = "QUERY")]
+    list_cities: Option<String>,
+    /// The day the week command's weeks start on
+    #[arg(long, value_enum, default_value = "mon")]
+    week_start: WeekStart,
+    /// In JSON and CSV output, also give civil, nautical and astronomical dawn and dusk. Days
+    /// the sun doesn't reach a twilight's depression have null (or empty) times for it
+    #[arg(long)]
+    twilight: bool,
+    /// In ICS output, add an alarm to each event that goes off this many minutes before it
+    #[arg(long, value_name = "MINUTES")]
+    reminder: Option<u32>,
 }
 
-#[derive(Parser)]
+const DEFAULT_DATE_FORMAT: &str = "%Y-%m-%d";
+const DEFAULT_TIME_FORMAT: &str = "%H:%M:%S";
+
+/// A strftime format string given on the command line, checked when it's parsed so a typo
+/// fails up front rather than garbling (or panicking partway through) the output
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct TimeFormat(String);
+
+impl FromStr for TimeFormat {
+    type Err = LocationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if StrftimeItems::new(s).any(|item| item == Item::Error) {
+            return Err(LocationError::BadTimeFormat(s.to_string()));
+        }
+        Ok(TimeFormat(s.to_string()))
+    }
+}
+
+/// A time zone given on the command line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ZoneArg {
+    Fixed(FixedOffset),
+    Named(Tz),
+}
+
+impl FromStr for ZoneArg {
+    type Err = LocationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let unknown = || LocationError::UnknownTimeZone(s.to_string());
+        let (sign, rest) = match s.as_bytes().first() {
+            Some(b'+') => (1, &s[1..]),
+            Some(b'-') => (-1, &s[1..]),
+            _ => return s.parse().map(ZoneArg::Named).map_err(|_| unknown()),
+        };
+        let (hours, minutes) = rest.split_once(':').unwrap_or((rest, "0"));
+        let hours: i32 = hours.parse().map_err(|_| unknown())?;
+        let minutes: i32 = minutes.parse().map_err(|_| unknown())?;
+        if !(0..60).contains(&minutes) {
+            return Err(unknown());
+        }
+        FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+            .map(ZoneArg::Fixed)
+            .ok_or_else(unknown)
+    }
+}
+
+impl Args {
+    fn plot_style(&self) -> PlotStyle {
+        PlotStyle {
+            width: self.width.unwrap_or(120),
+            height: self.height.unwrap_or(10),
+            glyphs: if self.ascii {
+                Glyphs::Ascii
+            } else {
+                Glyphs::Braille
+            },
+            grid: self.grid,
+            color: self.color(),
+        }
+    }
+
+    fn human_style(&self) -> HumanStyle<'_> {
+        HumanStyle {
+            markers: self.markers(),
+            day_length: self.show_day_length,
+            color: self.color(),
+            moon: self.moon,
+            shadow: self.shadow,
+            relative_to: self.relative.then(|| Local::now().naive_local().date()),
+            date_format: &self.date_format.0,
+            time_format: &self.time_format.0,
+        }
+    }
+
+    fn color(&self) -> bool {
+        match self.color {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => self.output.is_none() && io::stdout().is_terminal(),
+        }
+    }
+
+    /// Where output goes: the --output file, or stdout
+    fn writer(&self) -> Result<Box<dyn Write>, Box<dyn Error>> {
+        Ok(match &self.output {
+            Some(path) => {
+                let file = File::create(path)
+                    .map_err(|err| format!("Unable to create {}: {err}", path.display()))?;
+                Box::new(BufWriter::new(file))
+            }
+            None => Box::new(io::stdout()),
+        })
+    }
+
+    fn markers(&self) -> Markers {
+        if self.no_emoji || !terminal_supports_emoji() {
+            Markers::Ascii
+        } else {
+            Markers::Emoji
+        }
+    }
+
+    /// How to show times at `pos`. Unless told otherwise, that's the location's own time zone,
+    /// falling back to UTC (with a warning) where it can't be determined.
+    fn clock(&self, pos: Pos) -> Clock {
+        let utc = ZoneArg::Fixed(FixedOffset::east_opt(0).expect("Zero offset is in range"));
+        if self.true_solar {
+            Clock::TrueSolar
+        } else if self.utc {
+            Clock::Zone(utc)
+        } else if let Some(zone) = self.tz {
+            Clock::Zone(zone)
+        } else if self.local_time {
+            Clock::Local
+        } else if let Some(zone) = zone_at(pos) {
+            Clock::Zone(ZoneArg::Named(zone))
+        } else {
+            eprintln!("Warning: unable to find the time zone at {pos}; showing times in UTC");
+            Clock::Zone(utc)
+        }
+    }
+}
+
+#[derive(Parser, Clone, Copy)]
 enum Mode {
     /// Shows times for today
     Today,
@@ -75,252 +384,3055 @@ enum Mode {
     Next { days: u16 },
     /// Shows times for the previous given number of days
     Last { days: u16 },
+    /// Shows times for every date from --from to --to, inclusive
+    Range {
+        /// First date, as YYYY-MM-DD
+        #[arg(long)]
+        from: NaiveDate,
+        /// Last date, as YYYY-MM-DD
+        #[arg(long)]
+        to: NaiveDate,
+    },
+    /// Shows day length on a single date at a sweep of latitudes along --long (default: 0)
+    Sweep {
+        /// Date to compute, as YYYY-MM-DD. Default: today
+        #[arg(long)]
+        date: Option<NaiveDate>,
+        /// Degrees of latitude between rows
+        #[arg(long, default_value = "10")]
+        step: f64,
+        /// Also show sunrise and sunset (in UTC) at each latitude, tracing the terminator along
+        /// the meridian
+        #[arg(long)]
+        events: bool,
+    },
+    /// Shows the equation of time (the sundial correction) for each day of a year
+    Eot {
+        /// Year to tabulate. Default: the current year
+        year: Option<i32>,
+        /// Also show the sun's declination
+        #[arg(long)]
+        declination: bool,
+    },
+    /// Shows when the equinoxes and solstices fall in a year, in UTC
+    Events {
+        /// Year to search. Default: the current year
+        year: Option<i32>,
+    },
+    /// Draws a map of where on Earth it is day and night. Map size follows --width (default: 72)
+    /// and --height (default: 24)
+    Map {
+        /// Instant to show, in RFC 3339 form. Default: now
+        #[arg(long)]
+        at: Option<DateTime<Utc>>,
+    },
+    /// Shows how long until the next sunrise or sunset, whichever comes first. Supports human and
+    /// json output
+    Until,
+    /// Exits with status 0 if every given condition holds right now, and 1 otherwise, for use in
+    /// scripts (e.g. `suntime check --during golden-hour && take-photo`)
+    Check {
+        /// True while the sun is in the given part of the day
+        #[arg(long)]
+        during: Option<Period>,
+        /// True once today's given event has happened
+        #[arg(long)]
+        after: Option<Event>,
+        /// True until today's given event happens
+        #[arg(long)]
+        before: Option<Event>,
+        /// Print each condition's result to stderr
+        #[arg(short, long)]
+        verbose: bool,
+    },
+    /// Shows the dates of a year's earliest and latest sunrises and sunsets, which the equation
+    /// of time moves days or weeks away from the solstices. Supports human, csv and json output
+    Extremes {
+        /// Year to search. Default: the current year
+        year: Option<i32>,
+    },
+}
+
+#[derive(Error, Debug)]
+enum ModeError {
+    #[error("The range starts on {0}, after it ends on {1}")]
+    BackwardsRange(NaiveDate, NaiveDate),
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+enum ColorChoice {
+    /// Colour output only when it goes to a terminal
+    Auto,
+    Always,
+    Never,
+}
+
+/// Series `--format plot` can draw for a range of days
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+enum PlotSeries {
+    /// Sunrise and sunset times
+    SunTimes,
+    /// The time from sunrise to sunset
+    DayLength,
+}
+
+/// The first day of a week
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum WeekStart {
+    Mon,
+    Sun,
+}
+
+/// The week containing `date`, starting on `week_start`
+fn week_of(date: NaiveDate, week_start: WeekStart) -> RangeInclusive<NaiveDate> {
+    let day_of_week = match week_start {
+        WeekStart::Mon => date.weekday().num_days_from_monday(),
+        WeekStart::Sun => date.weekday().num_days_from_sunday(),
+    };
+    let start = date - Duration::days(day_of_week as i64);
+    start..=start + Duration::days(6)
+}
+
+#[test]
+fn test_week_of() {
+    let date = |d| NaiveDate::from_ymd_opt(2023, 3, d).unwrap();
+    // 2023-03-15 was a Wednesday
+    assert_eq!(week_of(date(15), WeekStart::Mon), date(13)..=date(19));
+    assert_eq!(week_of(date(15), WeekStart::Sun), date(12)..=date(18));
+    // A Sunday ends a Monday week and starts a Sunday one
+    assert_eq!(week_of(date(19), WeekStart::Mon), date(13)..=date(19));
+    assert_eq!(week_of(date(19), WeekStart::Sun), date(19)..=date(25));
+}
+
+/// Parts of the day by the sun's elevation, as checked by `check --during`
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+enum Period {
+    /// The sun is above the horizon
+    Day,
+    /// The sun is below the horizon
+    Night,
+    /// The sun is between 4° below and 6° above the horizon, for warm, soft light
+    GoldenHour,
+    /// The sun is between 6° and 4° below the horizon, for deep blue skies
+    BlueHour,
+    /// The sun is below the horizon but no more than 6° below it
+    CivilTwilight,
+}
+
+impl Period {
+    /// Whether the sun being `elevation` degrees above the horizon falls in this period, where
+    /// it rises and sets at an elevation of `horizon`
+    fn contains(self, elevation: f64, horizon: f64) -> bool {
+        match self {
+            Period::Day => elevation >= horizon,
+            Period::Night => elevation < horizon,
+            Period::GoldenHour => (-4. ..=6.).contains(&elevation),
+            Period::BlueHour => (-6. ..-4.).contains(&elevation),
+            Period::CivilTwilight => (-6. ..horizon).contains(&elevation),
+        }
+    }
+}
+
+/// Daily events for `check --after` and `--before`
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+enum Event {
+    Sunrise,
+    Noon,
+    Sunset,
+}
+
+impl Event {
+    /// When the event happens on the observer's day containing `dt`, or `None` if the sun
+    /// doesn't rise or set. The day runs between local mean midnights at `pos`'s longitude, so
+    /// an evening check west of Greenwich looks at that evening's sunset, not the next UTC day's.
+    fn time(self, pos: Pos, dt: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let local = dt + Duration::seconds((pos.long() * 240.) as i64);
+        // Solar noon on that local date falls on the same UTC date
+        let twelve = local.naive_utc().date().and_hms_opt(12, 0, 0);
+        let noon = pos.solar_noon(Utc.from_utc_datetime(&twelve.expect("Noon is a valid time")));
+        match self {
+            Event::Sunrise => pos.try_sunrise(noon).ok(),
+            Event::Noon => Some(noon),
+            Event::Sunset => pos.try_sunset(noon).ok(),
+        }
+    }
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
-    let args = Args::parse();
-    let pos = validate_location(&args)?;
+    let matches = Args::command().get_matches();
+    let mut args = Args::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
+    Config::load()?.apply(&mut args, &matches);
+    if args.header_only {
+        let mut out = args.writer()?;
+        writeln!(out, "{}", csv_header(&args))?;
+        out.flush()?;
+        return Ok(());
+    }
+    if let Some(query) = &args.list_cities {
+        let cities = list_cities(query, &args)?;
+        let mut out = args.writer()?;
+        writeln!(out, "{}", serde_json::to_string_pretty(&cities).unwrap())?;
+        out.flush()?;
+        return Ok(());
+    }
     let mode = args.mode.unwrap_or(Mode::Today);
 
-    let today = Local::now()
-        .with_hour(12)
-        .unwrap()
-        .with_minute(0)
-        .unwrap()
-        .with_second(0)
-        .unwrap()
-        .with_timezone(&Utc);
-    match mode {
-        Mode::Today => output_range(
-            DateIter::new(today, today),
-            pos,
-            args.width,
-            args.height,
-            args.format,
-        ),
-        Mode::Week => {
-            let day_of_week = today.weekday().num_days_from_monday() as i64;
-            output_range(
-                DateIter::new(
-                    today - Duration::days(day_of_week),
-                    today + Duration::days(6 - day_of_week),
-                ),
-                pos,
-                args.width,
-                args.height,
-                args.format,
-            );
+    let today = today();
+    if let Mode::Sweep { date, step, events } = mode {
+        let date = date.map_or(today, |date| {
+            Utc.from_utc_datetime(&date.and_hms_opt(12, 0, 0).expect("Noon is a valid time"))
+        });
+        return output_sweep(
+            date,
+            args.long.first().copied().unwrap_or(0.),
+            step,
+            events,
+            &args,
+        );
+    }
+    if let Mode::Eot { year, declination } = mode {
+        return output_eot(year.unwrap_or(today.year()), declination, &args);
+    }
+    if let Mode::Events { year } = mode {
+        return output_seasons(year.unwrap_or(today.year()), &args);
+    }
+    if let Mode::Map { at } = mode {
+        let at = at.unwrap_or_else(Utc::now);
+        let rows = plot_daylight_map(
+            args.width.unwrap_or(72),
+            args.height.unwrap_or(24),
+            |lat, long| Pos::new(lat, long).solar_position(at).0,
+        );
+        let mut out = args.writer()?;
+        writeln!(out, "{}", at.format("%Y-%m-%d %H:%M UTC"))?;
+        for row in rows {
+            writeln!(out, "{row}")?;
+        }
+        out.flush()?;
+        return Ok(());
+    }
+    if let Mode::Range { from, to } = mode {
+        if from > to {
+            return Err(ModeError::BackwardsRange(from, to).into());
+        }
+    }
+    let positions = validate_location(&args)?;
+    let pos = positions[0];
+    let table = matches!(
+        mode,
+        Mode::Today
+            | Mode::Week
+            | Mode::Year
+            | Mode::Next { .. }
+            | Mode::Last { .. }
+            | Mode::Range { .. }
+    ) || matches!(mode, Mode::Month if !args.calendar);
+    if positions.len() > 1 && (!table || args.watch.is_some() || args.daytime) {
+        return Err("Only tables of days can compare two locations".into());
+    }
+    if args.daytime {
+        let daytime = pos.is_daytime(Utc::now());
+        let mut out = args.writer()?;
+        writeln!(out, "{daytime}")?;
+        // Exiting skips destructors, so nothing would flush a buffered file
+        out.flush()?;
+        std::process::exit(if daytime { 0 } else { 1 });
+    }
+    if let Some(interval) = args.watch {
+        if !matches!(mode, Mode::Today) {
+            return Err("--watch only works with the today command".into());
         }
+        return watch(pos, interval, &args);
+    }
+    let today_date = today.naive_utc().date();
+    match mode {
+        Mode::Today => output_range(noons(today_date..=today_date), &positions, &args)?,
+        Mode::Week => output_range(
+            noons(week_of(today_date, args.week_start)),
+            &positions,
+            &args,
+        )?,
         Mode::Month => {
-            let month_start = today.with_day(1).unwrap();
-            let month_end = today
+            let month_start = today_date.with_day(1).unwrap();
+            let month_end = today_date
                 .with_day(31)
-                .or_else(|| today.with_day(30))
-                .or_else(|| today.with_day(29))
-                .or_else(|| today.with_day(28))
+                .or_else(|| today_date.with_day(30))
+                .or_else(|| today_date.with_day(29))
+                .or_else(|| today_date.with_day(28))
                 .expect("Shortest month has 28 days");
 
-            output_range(
-                DateIter::new(month_start, month_end),
-                pos,
-                args.width,
-                args.height,
-                args.format,
-            )
+            if args.calendar {
+                let clock = args.clock(pos);
+                let days: Vec<_> = noons(month_start..=month_end)
+                    .map(|dt| (dt.naive_utc().date(), SunTimes::from_pos(dt, pos, clock)))
+                    .collect();
+                let mut out = args.writer()?;
+                for row in render_calendar(&days) {
+                    writeln!(out, "{row}")?;
+                }
+                out.flush()?;
+            } else {
+                output_range(noons(month_start..=month_end), &positions, &args)?
+            }
         }
         Mode::Year => {
-            let year_start = today.with_ordinal(1).unwrap();
-            let year_end = today
+            let year_start = today_date.with_ordinal(1).unwrap();
+            let year_end = today_date
                 .with_ordinal(366)
-                .or_else(|| today.with_ordinal(365))
+                .or_else(|| today_date.with_ordinal(365))
                 .expect("At least 365 days per year");
-            output_range(
-                DateIter::new(year_start, year_end),
-                pos,
-                args.width,
-                args.height,
-                args.format,
-            )
+            output_range(noons(year_start..=year_end), &positions, &args)?
         }
         Mode::Next { days } => output_range(
-            DateIter::new(today, today + Duration::days(days as i64)),
-            pos,
-            args.width,
-            args.height,
-            args.format,
-        ),
+            noons(today_date..=today_date + Duration::days(days as i64)),
+            &positions,
+            &args,
+        )?,
         Mode::Last { days } => output_range(
-            DateIter::new(today - Duration::days(days as i64 - 1), today),
-            pos,
-            args.width,
-            args.height,
-            args.format,
-        ),
+            noons(today_date - Duration::days(days as i64 - 1)..=today_date),
+            &positions,
+            &args,
+        )?,
+        Mode::Range { from, to } => output_range(noons(from..=to), &positions, &args)?,
+        Mode::Until => {
+            let countdown = Countdown::new(pos, Utc::now())
+                .ok_or("The sun neither rises nor sets here within the next year")?;
+            let mut out = args.writer()?;
+            match args.format {
+                Format::Human => writeln!(out, "{countdown}")?,
+                Format::Json => writeln!(out, "{}", serde_json::to_string(&countdown).unwrap())?,
+                _ => return Err("until only supports human and json output".into()),
+            }
+            out.flush()?;
+        }
+        Mode::Check {
+            during,
+            after,
+            before,
+            verbose,
+        } => {
+            let holds = check_now(pos, Utc::now(), during, after, before, verbose);
+            std::process::exit(if holds { 0 } else { 1 });
+        }
+        Mode::Extremes { year } => output_extremes(year.unwrap_or(today.year()), pos, &args)?,
+        Mode::Sweep { .. } | Mode::Eot { .. } | Mode::Events { .. } | Mode::Map { .. } => {
+            unreachable!("Handled before resolving the location")
+        }
     }
 
     Ok(())
 }
 
-fn output_range<I: Iterator<Item = DateTime<Utc>>>(
-    range: I,
+/// Evaluates each `check` condition at `now`, returning whether they all hold. Conditions on an
+/// event that doesn't happen today (sunrise during polar night, say) never hold.
+fn check_now(
     pos: Pos,
-    width: Option<usize>,
-    height: Option<usize>,
-    format: Format,
-) {
-    match format {
-        Format::Human => range.for_each(|date| human_output(date, pos)),
-        Format::Csv => range.for_each(|date| csv_output(date, pos)),
-        Format::Plot => {
-            let output: Vec<_> = range.map(|dt| SunTimes::from_pos(dt, pos)).collect();
-            let sunsets: Vec<_> = output.iter().map(|s| s.sunset).collect();
-            plot_times(
-                "Sunsets",
-                width.unwrap_or(120),
-                height.unwrap_or(10),
-                &sunsets,
-            );
-            let sunrises: Vec<_> = output.iter().map(|s| s.sunrise).collect();
-            plot_times(
-                "Sunrises",
-                width.unwrap_or(120),
-                height.unwrap_or(10),
-                &sunrises,
-            );
-        }
-        Format::Json => {
-            let output: Vec<_> = range.map(|dt| SunTimes::from_pos(dt, pos)).collect();
-            println!("{}", serde_json::to_string_pretty(&output).unwrap());
+    now: DateTime<Utc>,
+    during: Option<Period>,
+    after: Option<Event>,
+    before: Option<Event>,
+    verbose: bool,
+) -> bool {
+    let elevation = pos.solar_position(now).0;
+    let mut results = vec![];
+    if let Some(period) = during {
+        let holds = period.contains(elevation, pos.horizon_elevation());
+        results.push((format!("during {period:?}"), holds));
+    }
+    if let Some(event) = after {
+        let holds = event.time(pos, now).is_some_and(|time| now >= time);
+        results.push((format!("after {event:?}"), holds));
+    }
+    if let Some(event) = before {
+        let holds = event.time(pos, now).is_some_and(|time| now < time);
+        results.push((format!("before {event:?}"), holds));
+    }
+    if verbose {
+        eprintln!("Sun at {elevation:.1}°");
+        for (condition, holds) in &results {
+            eprintln!("{condition}: {holds}");
         }
     }
+    results.iter().all(|(_, holds)| *holds)
 }
 
-struct DateIter {
-    today: DateTime<Utc>,
-    target: DateTime<Utc>,
+/// Noon today, in the machine's time zone
+fn today() -> DateTime<Utc> {
+    Local::now()
+        .with_hour(12)
+        .unwrap()
+        .with_minute(0)
+        .unwrap()
+        .with_second(0)
+        .unwrap()
+        .with_timezone(&Utc)
+}
+
+#[derive(Serialize)]
+struct Countdown {
+    event: &'static str,
+    seconds: i64,
 }
-impl Iterator for DateIter {
-    type Item = DateTime<Utc>;
 
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.today <= self.target {
-            let rv = self.today;
-            self.today += Duration::days(1);
-            Some(rv)
+impl Countdown {
+    /// Time from `now` to the next sunrise or sunset, if there's one within a year
+    fn new(pos: Pos, now: DateTime<Utc>) -> Option<Self> {
+        let (event, time) = next_sun_event(pos, now)?;
+        let event = match event {
+            Event::Sunrise => "sunrise",
+            Event::Noon => "noon",
+            Event::Sunset => "sunset",
+        };
+        Some(Countdown {
+            event,
+            seconds: (time - now).num_seconds(),
+        })
+    }
+}
+
+impl std::fmt::Display for Countdown {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (hours, minutes) = (self.seconds / 3600, self.seconds / 60 % 60);
+        if hours > 0 {
+            write!(f, "{hours}h {minutes}m until {}", self.event)
         } else {
-            None
+            write!(f, "{minutes}m until {}", self.event)
         }
     }
 }
 
-impl DateIter {
-    pub fn new(start: DateTime<Utc>, end: DateTime<Utc>) -> Self {
-        DateIter {
-            today: start,
-            target: end,
+#[derive(Serialize)]
+struct WatchFrame {
+    #[serde(flatten)]
+    times: SunTimes,
+    next: Option<Countdown>,
+}
+
+/// Reprints today's times and the countdown to the next event every `interval` seconds, until
+/// Ctrl-C. Human output redraws the screen in place; JSON output appends one line per refresh.
+fn watch(pos: Pos, interval: u64, args: &Args) -> Result<(), Box<dyn Error>> {
+    if !matches!(args.format, Format::Human | Format::Json) {
+        return Err("--watch only supports human and json output".into());
+    }
+    let (stop, stopped) = std::sync::mpsc::channel();
+    ctrlc::set_handler(move || {
+        let _ = stop.send(());
+    })?;
+    let clock = args.clock(pos);
+    let mut out = args.writer()?;
+    loop {
+        let today = today();
+        let countdown = Countdown::new(pos, Utc::now());
+        match args.format {
+            Format::Json => {
+                let frame = WatchFrame {
+                    times: SunTimes::from_pos(today, pos, clock),
+                    next: countdown,
+                };
+                writeln!(out, "{}", serde_json::to_string(&frame).unwrap())?;
+            }
+            _ => {
+                // Clear the screen and move the cursor to the top-left
+                write!(out, "\x1b[2J\x1b[H")?;
+                human_output(&mut out, today, pos, clock, args.human_style())?;
+                if let Some(countdown) = countdown {
+                    writeln!(out, "{countdown}")?;
+                }
+            }
+        }
+        out.flush()?;
+        match stopped.recv_timeout(std::time::Duration::from_secs(interval)) {
+            Err(std::sync::mpsc::RecvTimeoutError::Timeout) => continue,
+            _ => return Ok(()),
         }
     }
 }
 
-fn format_duration_ms(duration: Duration) -> String {
-    format!(
-        "{}{}:{:02}",
-        if duration.num_seconds() < 0 { "-" } else { "" },
-        duration.num_minutes().abs(),
-        (duration.num_seconds() % 60).abs()
-    )
+/// The first sunrise or sunset after `now`, or `None` if there's neither within a year
+fn next_sun_event(pos: Pos, now: DateTime<Utc>) -> Option<(Event, DateTime<Utc>)> {
+    let sunrise = pos.next_sunrise(now).map(|time| (Event::Sunrise, time));
+    let sunset = pos.next_sunset(now).map(|time| (Event::Sunset, time));
+    [sunrise, sunset]
+        .into_iter()
+        .flatten()
+        .min_by_key(|(_, time)| *time)
 }
 
-fn format_duration_hms(duration: Duration) -> String {
-    format!(
-        "{}{}:{:02}:{:02}",
-        if duration.num_seconds() < 0 { "-" } else { "" },
-        duration.num_hours(),
-        duration.num_minutes().abs() % 60,
-        (duration.num_seconds() % 60).abs()
-    )
+#[test]
+fn test_next_sun_event() {
+    let pos = Pos::new(51.5, 0.);
+    let at = |hour| Utc.with_ymd_and_hms(2023, 3, 20, hour, 0, 0).unwrap();
+    let (event, time) = next_sun_event(pos, at(12)).unwrap();
+    assert_eq!(event, Event::Sunset);
+    assert!((6..7).contains(&(time - at(12)).num_hours()), "{time}");
+    let (event, time) = next_sun_event(pos, at(0)).unwrap();
+    assert_eq!(event, Event::Sunrise);
+    assert!((5..7).contains(&(time - at(0)).num_hours()), "{time}");
+    // After sunset, the next event is tomorrow's sunrise
+    let (event, _) = next_sun_event(pos, at(22)).unwrap();
+    assert_eq!(event, Event::Sunrise);
 }
 
-#[derive(Debug, Serialize)]
-struct SunTimes {
-    #[serde(serialize_with = "serialize_dt")]
-    sunrise: DateTime<FixedOffset>,
-    #[serde(serialize_with = "serialize_dt")]
-    noon: DateTime<FixedOffset>,
-    #[serde(serialize_with = "serialize_dt")]
-    sunset: DateTime<FixedOffset>,
+#[test]
+fn test_countdown() {
+    let pos = Pos::new(51.5, 0.);
+    let at = |hour| Utc.with_ymd_and_hms(2023, 3, 20, hour, 0, 0).unwrap();
+    let countdown = Countdown::new(pos, at(12)).unwrap();
+    assert_eq!(countdown.event, "sunset");
+    assert!(countdown.to_string().starts_with("6h "), "{countdown}");
+    let countdown = Countdown {
+        event: "sunrise",
+        seconds: 125,
+    };
+    assert_eq!(countdown.to_string(), "2m until sunrise");
 }
 
-fn serialize_dt<S>(value: &DateTime<FixedOffset>, serializer: S) -> Result<S::Ok, S::Error>
-where
-    S: Serializer,
-{
-    value.to_rfc3339().serialize(serializer)
+#[test]
+fn test_check_elevated() {
+    // From a mountaintop the sun sets later, over a horizon that dips below the horizontal
+    let dt = Utc.with_ymd_and_hms(2023, 3, 20, 17, 0, 0).unwrap();
+    let (valley, summit) = (Pos::new(40., -75.), Pos::with_elevation(40., -75., 2000.));
+    let dusk = summit.sunset(dt) - Duration::minutes(1);
+    assert!(dusk > valley.sunset(dt));
+    assert!(check_now(
+        summit,
+        dusk,
+        Some(Period::Day),
+        None,
+        None,
+        false
+    ));
+    assert!(check_now(
+        summit,
+        dusk,
+        None,
+        None,
+        Some(Event::Sunset),
+        false
+    ));
+    assert!(check_now(
+        valley,
+        dusk,
+        Some(Period::Night),
+        None,
+        None,
+        false
+    ));
+    assert!(check_now(
+        valley,
+        dusk,
+        None,
+        Some(Event::Sunset),
+        None,
+        false
+    ));
 }
 
-impl SunTimes {
-    fn from_pos(dt: DateTime<Utc>, pos: Pos) -> Self {
-        let tz = chrono::FixedOffset::east_opt(Local::now().offset().local_minus_utc())
-            .expect("Offset obtained from Chrono won't be out-of-bounds");
-        let dt = pos.solar_noon(dt);
-        let noon = pos.solar_noon(dt).with_timezone(&tz);
-        let sunrise = pos.sunrise(dt).with_timezone(&tz);
-        let sunset = pos.sunset(dt).with_timezone(&tz);
-        SunTimes {
-            sunrise,
-            noon,
-            sunset,
+#[test]
+fn test_check_now() {
+    let pos = Pos::new(40., -75.);
+    let noon = pos.solar_noon(Utc.with_ymd_and_hms(2023, 3, 20, 17, 0, 0).unwrap());
+    let sunset = pos.sunset(noon);
+    assert!(check_now(pos, noon, Some(Period::Day), None, None, false));
+    assert!(!check_now(
+        pos,
+        noon,
+        Some(Period::GoldenHour),
+        None,
+        None,
+        false
+    ));
+    assert!(check_now(
+        pos,
+        sunset,
+        Some(Period::GoldenHour),
+        None,
+        None,
+        false
+    ));
+    assert!(check_now(
+        pos,
+        noon,
+        None,
+        Some(Event::Sunrise),
+        Some(Event::Sunset),
+        false
+    ));
+    assert!(!check_now(
+        pos,
+        sunset + Duration::minutes(5),
+        None,
+        None,
+        Some(Event::Sunset),
+        false
+    ));
+    // No conditions trivially hold
+    assert!(check_now(pos, noon, None, None, None, false));
+}
+
+#[test]
+fn test_check_evening_off_greenwich() {
+    // Evenings at ±120° fall on a different UTC day from the local one at one or the other
+    let check = |pos, now, after, before| check_now(pos, now, None, after, before, false);
+    let west = Pos::new(34., -120.);
+    let evening = Utc.with_ymd_and_hms(2023, 6, 3, 5, 0, 0).unwrap();
+    let sunset = west.sunset(Utc.with_ymd_and_hms(2023, 6, 2, 12, 0, 0).unwrap());
+    assert_eq!(Event::Sunset.time(west, evening), Some(sunset));
+    assert!(west.solar_position(evening).0 < 0.);
+    assert!(check(west, evening, Some(Event::Sunset), None));
+    assert!(!check(west, evening, None, Some(Event::Sunset)));
+    assert!(check(
+        west,
+        sunset - Duration::minutes(30),
+        None,
+        Some(Event::Sunset)
+    ));
+
+    let east = Pos::new(-34., 120.);
+    let evening = Utc.with_ymd_and_hms(2023, 6, 2, 12, 0, 0).unwrap();
+    let sunset = east.sunset(evening);
+    assert_eq!(Event::Sunset.time(east, evening), Some(sunset));
+    assert!(check(east, evening, Some(Event::Sunset), None));
+    assert!(!check(east, evening, None, Some(Event::Sunset)));
+    // Just before dawn the next morning, which is still the previous day in UTC
+    let dawn = Utc.with_ymd_and_hms(2023, 6, 2, 22, 30, 0).unwrap();
+    let sunrise = east.sunrise(Utc.with_ymd_and_hms(2023, 6, 3, 12, 0, 0).unwrap());
+    assert_eq!(Event::Sunrise.time(east, dawn), Some(sunrise));
+    assert!(check(east, dawn, None, Some(Event::Sunrise)));
+    assert!(!check(east, dawn, Some(Event::Sunset), None));
+}
+
+#[derive(Serialize)]
+struct SweepRow {
+    latitude: f64,
+    day_length_s: i64,
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    events: Option<SweepEvents>,
+}
+
+#[derive(Serialize)]
+struct SweepEvents {
+    sunrise: Option<String>,
+    sunset: Option<String>,
+}
+
+fn output_sweep(
+    date: DateTime<Utc>,
+    long: f64,
+    step: f64,
+    events: bool,
+    args: &Args,
+) -> Result<(), Box<dyn Error>> {
+    if step <= 0. {
+        return Err("Sweep step must be a positive number of degrees".into());
+    }
+    let lats: Vec<_> = (0..)
+        .map(|i| -90. + i as f64 * step)
+        .take_while(|lat| *lat <= 90.)
+        .collect();
+    let rise_set = meridian_sunrise_sunset(long, date, &lats);
+    let rows: Vec<_> = lats
+        .iter()
+        .zip(&rise_set)
+        .map(|(lat, rise_set)| {
+            (
+                *lat,
+                Pos::new(*lat, long).day_length(date),
+                events.then_some(*rise_set),
+            )
+        })
+        .collect();
+    let format_time = |time: Option<DateTime<Utc>>, pattern: &str| {
+        time.map_or(String::new(), |time| time.format(pattern).to_string())
+    };
+    let mut out = args.writer()?;
+    match args.format {
+        Format::Human => {
+            let markers = args.markers();
+            for (lat, length, rise_set) in &rows {
+                match rise_set {
+                    Some(Twilight { dawn, dusk }) => writeln!(
+                        out,
+                        "{lat:>6.1}° {} {} {:>8} {} {:>8}",
+                        format_duration_hms(*length),
+                        markers.sunrise(),
+                        format_time(*dawn, "%H:%M:%S"),
+                        markers.sunset(),
+                        format_time(*dusk, "%H:%M:%S")
+                    )?,
+                    None => writeln!(out, "{lat:>6.1}° {}", format_duration_hms(*length))?,
+                }
+            }
+        }
+        Format::Csv => {
+            if events {
+                writeln!(out, "latitude,day_length_s,sunrise,sunset")?;
+            } else {
+                writeln!(out, "latitude,day_length_s")?;
+            }
+            for (lat, length, rise_set) in &rows {
+                match rise_set {
+                    Some(Twilight { dawn, dusk }) => writeln!(
+                        out,
+                        "{lat},{},{},{}",
+                        length.num_seconds(),
+                        format_time(*dawn, "%+"),
+                        format_time(*dusk, "%+")
+                    )?,
+                    None => writeln!(out, "{lat},{}", length.num_seconds())?,
+                }
+            }
+        }
+        Format::Json => {
+            let rows: Vec<_> = rows
+                .iter()
+                .map(|(latitude, length, rise_set)| SweepRow {
+                    latitude: *latitude,
+                    day_length_s: length.num_seconds(),
+                    events: rise_set.map(|Twilight { dawn, dusk }| SweepEvents {
+                        sunrise: dawn.map(|dt| dt.to_rfc3339()),
+                        sunset: dusk.map(|dt| dt.to_rfc3339()),
+                    }),
+                })
+                .collect();
+            writeln!(out, "{}", serde_json::to_string_pretty(&rows).unwrap())?;
+        }
+        Format::Plot if events => {
+            // Each column is a latitude, south to north; polar latitudes are left out
+            let utc = FixedOffset::east_opt(0).expect("Zero offset is in range");
+            let sunsets: Vec<_> = rise_set
+                .iter()
+                .filter_map(|rise_set| rise_set.dusk)
+                .map(|dt| dt.with_timezone(&utc))
+                .collect();
+            let sunrises: Vec<_> = rise_set
+                .iter()
+                .filter_map(|rise_set| rise_set.dawn)
+                .map(|dt| dt.with_timezone(&utc))
+                .collect();
+            if sunrises.is_empty() {
+                return Err("The sun neither rises nor sets anywhere along this meridian".into());
+            }
+            plot_times(&mut out, "Sunsets", &[], &sunsets, args.plot_style())?;
+            plot_times(&mut out, "Sunrises", &[], &sunrises, args.plot_style())?;
+        }
+        Format::Plot => {
+            let lengths: Vec<_> = rows.iter().map(|(_, length, _)| *length).collect();
+            writeln!(out, "{}", sparkline(&lengths))?;
+        }
+        Format::Ics => return Err("ICS output is only available for daily events".into()),
+        Format::Svg => return Err("SVG output is only available for a range of days".into()),
+        Format::Html | Format::Markdown => {
+            let mut columns = vec![("latitude", "Latitude"), ("day-length", "Day length")];
+            if events {
+                columns.extend([("sunrise", "Sunrise (UTC)"), ("sunset", "Sunset (UTC)")]);
+            }
+            let rows: Vec<_> = rows
+                .iter()
+                .map(|(lat, length, rise_set)| {
+                    let mut row = vec![format!("{lat:.1}°"), format_duration_hms(*length)];
+                    if let Some(Twilight { dawn, dusk }) = rise_set {
+                        row.push(format_time(*dawn, "%H:%M:%S"));
+                        row.push(format_time(*dusk, "%H:%M:%S"));
+                    }
+                    row
+                })
+                .collect();
+            if args.format == Format::Markdown {
+                writeln!(out, "{}", markdown_table(&columns, &rows))?;
+            } else {
+                let caption = format!(
+                    "Day length by latitude along {long}° on {}",
+                    date.format("%Y-%m-%d")
+                );
+                writeln!(out, "{}", html_table(&caption, &columns, &rows))?;
+            }
         }
     }
-    fn day_length(&self) -> Duration {
-        self.sunset - self.sunrise
+    out.flush()?;
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct EotRow {
+    date: String,
+    eot_min: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    declination_deg: Option<f64>,
+}
+
+fn output_eot(year: i32, declination: bool, args: &Args) -> Result<(), Box<dyn Error>> {
+    let start = NaiveDate::from_ymd_opt(year, 1, 1);
+    let end = NaiveDate::from_ymd_opt(year, 12, 31);
+    let (start, end) = start.zip(end).ok_or("Year is out of range")?;
+    let rows: Vec<_> = noons(start..=end)
+        .map(|dt| (dt, equation_of_time(dt), solar_declination(dt)))
+        .collect();
+    let mut out = args.writer()?;
+    match args.format {
+        Format::Human => {
+            for (dt, eot, decl) in &rows {
+                if declination {
+                    writeln!(
+                        out,
+                        "{} {:>6} {:>+6.2}°",
+                        dt.format("%Y-%m-%d"),
+                        format_duration_ms(*eot),
+                        decl
+                    )?;
+                } else {
+                    writeln!(
+                        out,
+                        "{} {:>6}",
+                        dt.format("%Y-%m-%d"),
+                        format_duration_ms(*eot)
+                    )?;
+                }
+            }
+        }
+        Format::Csv => {
+            if declination {
+                writeln!(out, "date,eot_min,declination_deg")?;
+            } else {
+                writeln!(out, "date,eot_min")?;
+            }
+            for (dt, eot, decl) in &rows {
+                let eot = eot.num_milliseconds() as f64 / 60_000.;
+                if declination {
+                    writeln!(out, "{},{eot:.3},{decl:.3}", dt.format("%Y-%m-%d"))?;
+                } else {
+                    writeln!(out, "{},{eot:.3}", dt.format("%Y-%m-%d"))?;
+                }
+            }
+        }
+        Format::Json => {
+            let rows: Vec<_> = rows
+                .iter()
+                .map(|(dt, eot, decl)| EotRow {
+                    date: dt.format("%Y-%m-%d").to_string(),
+                    eot_min: eot.num_milliseconds() as f64 / 60_000.,
+                    declination_deg: declination.then_some(*decl),
+                })
+                .collect();
+            writeln!(out, "{}", serde_json::to_string_pretty(&rows).unwrap())?;
+        }
+        Format::Plot => {
+            // Plotted as the UTC time a sundial on the prime meridian reads noon
+            let utc = FixedOffset::east_opt(0).expect("Zero offset is in range");
+            let noons: Vec<_> = rows
+                .iter()
+                .map(|(dt, eot, _)| (*dt - *eot).with_timezone(&utc))
+                .collect();
+            let dates: Vec<_> = rows
+                .iter()
+                .map(|(dt, _, _)| dt.naive_utc().date())
+                .collect();
+            plot_times(&mut out, "Sundial noon", &dates, &noons, args.plot_style())?;
+        }
+        Format::Ics => return Err("ICS output is only available for daily events".into()),
+        Format::Svg => return Err("SVG output is only available for a range of days".into()),
+        Format::Html | Format::Markdown => {
+            let mut columns = vec![("date", "Date"), ("eot", "Equation of time")];
+            if declination {
+                columns.push(("declination", "Declination"));
+            }
+            let rows: Vec<_> = rows
+                .iter()
+                .map(|(dt, eot, decl)| {
+                    let mut row = vec![dt.format("%Y-%m-%d").to_string(), format_duration_ms(*eot)];
+                    if declination {
+                        row.push(format!("{decl:+.2}°"));
+                    }
+                    row
+                })
+                .collect();
+            if args.format == Format::Markdown {
+                writeln!(out, "{}", markdown_table(&columns, &rows))?;
+            } else {
+                writeln!(
+                    out,
+                    "{}",
+                    html_table(&format!("Equation of time, {year}"), &columns, &rows)
+                )?;
+            }
+        }
     }
+    out.flush()?;
+    Ok(())
 }
 
-fn human_output(dt: DateTime<Utc>, pos: Pos) {
-    let times = SunTimes::from_pos(dt, pos);
-    let tomorrow = SunTimes::from_pos(dt + Duration::days(1), pos);
+#[derive(Serialize)]
+struct SeasonRow {
+    event: &'static str,
+    time: String,
+}
 
-    let sunset_delta = (tomorrow.sunset - times.sunset) - Duration::days(1);
-    let sunrise_delta = (tomorrow.sunrise - times.sunrise) - Duration::days(1);
-    let day_length = times.day_length();
-    let tomorrow_day_length = tomorrow.day_length();
-    let day_length_delta = tomorrow_day_length - day_length;
+fn output_seasons(year: i32, args: &Args) -> Result<(), Box<dyn Error>> {
+    let seasons = seasons(year).ok_or("Year is out of range")?;
+    let events = [
+        ("March equinox", seasons.march_equinox),
+        ("June solstice", seasons.june_solstice),
+        ("September equinox", seasons.september_equinox),
+        ("December solstice", seasons.december_solstice),
+    ];
+    let mut out = args.writer()?;
+    match args.format {
+        Format::Human => {
+            for (event, time) in events {
+                writeln!(out, "{event:<17} {}", time.format("%Y-%m-%d %H:%M UTC"))?;
+            }
+        }
+        Format::Csv => {
+            writeln!(out, "event,time")?;
+            for (event, time) in events {
+                writeln!(out, "{event},{}", time.to_rfc3339())?;
+            }
+        }
+        Format::Json => {
+            let rows: Vec<_> = events
+                .iter()
+                .map(|&(event, time)| SeasonRow {
+                    event,
+                    time: time.to_rfc3339(),
+                })
+                .collect();
+            writeln!(out, "{}", serde_json::to_string_pretty(&rows).unwrap())?;
+        }
+        Format::Plot | Format::Svg => {
+            return Err("Plots are only available for a range of days".into())
+        }
+        Format::Ics => {
+            let events: Vec<_> = events
+                .iter()
+                .map(|&(event, time)| IcsEvent {
+                    uid: format!("{year}-{}@suntime", event.to_lowercase().replace(' ', "-")),
+                    summary: event.to_string(),
+                    start: time,
+                })
+                .collect();
+            write!(out, "{}", ics_calendar(&events, Utc::now(), args.reminder))?;
+        }
+        Format::Html | Format::Markdown => {
+            let columns = [("event", "Event"), ("time", "Time")];
+            let rows: Vec<_> = events
+                .iter()
+                .map(|(event, time)| {
+                    vec![
+                        event.to_string(),
+                        time.format("%Y-%m-%d %H:%M UTC").to_string(),
+                    ]
+                })
+                .collect();
+            if args.format == Format::Markdown {
+                writeln!(out, "{}", markdown_table(&columns, &rows))?;
+            } else {
+                writeln!(
+                    out,
+                    "{}",
+                    html_table(&format!("Equinoxes and solstices, {year}"), &columns, &rows)
+                )?;
+            }
+        }
+    }
+    out.flush()?;
+    Ok(())
+}
 
-    println!("{date} 🌅 {sunrise} (Δ{sunrise_delta:>5}) 🌞 {noon} ({day_length} Δ{day_length_delta:>5}) 🌇 {sunset} (Δ{sunset_delta:>5})",
-        date=dt.format("%Y-%m-%d"),
-        sunrise=times.sunrise.format("%H:%M:%S"),
-        sunrise_delta=format_duration_ms(sunrise_delta),
-        noon=times.noon.format("%H:%M:%S"),
-        day_length=format_duration_hms(day_length),
-        day_length_delta=format_duration_ms(day_length_delta),
-        sunset=times.sunset.format("%H:%M:%S"),
-        sunset_delta=format_duration_ms(sunset_delta)
-    );
+/// Writes times for each day of `range` at one location, or side by side for two
+fn output_range<I: Iterator<Item = DateTime<Utc>>>(
+    range: I,
+    positions: &[Pos],
+    args: &Args,
+) -> Result<(), Box<dyn Error>> {
+    let mut out = args.writer()?;
+    match positions {
+        [pos] => write_range(range, *pos, args, &mut out)?,
+        [first, second] => compare_output(range, (*first, *second), args, &mut out)?,
+        _ => unreachable!("validate_location gives one or two locations"),
+    }
+    out.flush()?;
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+struct LocatedSunTimes {
+    location: String,
+    #[serde(flatten)]
+    times: SunTimes,
 }
 
-fn csv_output(dt: DateTime<Utc>, pos: Pos) {
-    let times = SunTimes::from_pos(dt, pos);
+#[derive(Debug, Serialize)]
+struct ComparedSunTimes {
+    date: String,
+    locations: [LocatedSunTimes; 2],
+    /// How much later the sun rises in the second location than the first, within half a day
+    /// either way
+    sunrise_delta_s: i64,
+}
 
-    let day_start = times
-        .sunrise
-        .with_hour(0)
-        .unwrap()
-        .with_minute(0)
-        .unwrap()
-        .with_second(0)
-        .unwrap()
-        .with_nanosecond(0)
-        .unwrap();
+/// Writes each day's times at two locations next to each other, each in its own clock, along
+/// with the difference between their sunrises as read off those clocks
+fn compare_output<I: Iterator<Item = DateTime<Utc>>>(
+    range: I,
+    (first, second): (Pos, Pos),
+    args: &Args,
+    out: &mut dyn Write,
+) -> Result<(), Box<dyn Error>> {
+    let labels = match &args.city[..] {
+        [first, second] => [first.clone(), second.clone()],
+        _ => [first.to_string(), second.to_string()],
+    };
+    let (first_clock, second_clock) = (args.clock(first), args.clock(second));
+    let days: Vec<_> = range
+        .map(|dt| {
+            let times = [
+                SunTimes::from_pos(dt, first, first_clock),
+                SunTimes::from_pos(dt, second, second_clock),
+            ];
+            // Compared as instants, not clock readings, so zones don't skew it, and within half
+            // a day either way, so a pair either side of midnight UTC doesn't wrap
+            let half_day = 12 * 60 * 60;
+            let delta = (times[1].sunrise - times[0].sunrise).num_seconds();
+            let delta = Duration::seconds((delta + half_day).rem_euclid(2 * half_day) - half_day);
+            (dt, times, delta)
+        })
+        .collect();
+    let (date_format, time_format) = (&args.date_format.0, &args.time_format.0);
+    match args.format {
+        Format::Human => {
+            let markers = args.markers();
+            let name_width = labels.iter().map(String::len).max().unwrap_or_default();
+            for (dt, times, delta) in days {
+                let columns = labels.iter().zip(&times).map(|(label, times)| {
+                    format!(
+                        "{label:<name_width$} {} {} {} {}",
+                        markers.sunrise(),
+                        times.sunrise.format(time_format),
+                        markers.sunset(),
+                        times.sunset.format(time_format),
+                    )
+                });
+                let columns: Vec<_> = columns.collect();
+                writeln!(
+                    out,
+                    "{} {} | {} Δ{} {:>8}",
+                    dt.format(date_format),
+                    columns[0],
+                    columns[1],
+                    markers.sunrise(),
+                    format_duration_hms(delta)
+                )?;
+            }
+        }
+        Format::Csv => {
+            writeln!(out, "{}", compare_csv_header())?;
+            for (dt, _, delta) in days {
+                writeln!(
+                    out,
+                    "{},{},{},{}",
+                    dt.format(date_format),
+                    csv_times(dt, first, first_clock),
+                    csv_times(dt, second, second_clock),
+                    delta.num_seconds()
+                )?;
+            }
+        }
+        Format::Json => {
+            let output: Vec<_> = days
+                .into_iter()
+                .map(
+                    |(dt, [first_times, second_times], delta)| ComparedSunTimes {
+                        date: dt.format("%Y-%m-%d").to_string(),
+                        locations: [
+                            LocatedSunTimes {
+                                location: labels[0].clone(),
+                                times: first_times,
+                            },
+                            LocatedSunTimes {
+                                location: labels[1].clone(),
+                                times: second_times,
+                            },
+                        ],
+                        sunrise_delta_s: delta.num_seconds(),
+                    },
+                )
+                .collect();
+            writeln!(out, "{}", serde_json::to_string_pretty(&output).unwrap())?;
+        }
+        Format::Plot | Format::Html | Format::Ics | Format::Markdown | Format::Svg => {
+            return Err("Comparing locations only supports human, csv and json output".into())
+        }
+    }
+    Ok(())
+}
 
-    println!(
-        "{date},{sunrise},{noon},{sunset},{day_length}",
-        date = dt.format("%Y-%m-%d"),
-        sunrise = (times.sunrise - day_start).num_seconds(),
-        noon = (times.noon - day_start).num_seconds(),
-        sunset = (times.sunset - day_start).num_seconds(),
-        day_length = times.day_length().num_seconds()
-    );
+/// The accuracy warnings for `pos` in every year `dates` touch, each given once
+fn range_accuracy_warnings(pos: Pos, dates: &[DateTime<Utc>]) -> Vec<AccuracyWarning> {
+    let years: BTreeSet<_> = dates.iter().map(|dt| dt.year()).collect();
+    let mut warnings = vec![];
+    for warning in years
+        .into_iter()
+        .flat_map(|year| pos.accuracy_warnings(year))
+    {
+        if !warnings.contains(&warning) {
+            warnings.push(warning);
+        }
+    }
+    warnings
+}
+
+fn write_range<I: Iterator<Item = DateTime<Utc>>>(
+    range: I,
+    pos: Pos,
+    args: &Args,
+    out: &mut dyn Write,
+) -> Result<(), Box<dyn Error>> {
+    let clock = args.clock(pos);
+    let dates: Vec<_> = range.collect();
+    if args.warn_accuracy {
+        let warnings = range_accuracy_warnings(pos, &dates);
+        for warning in &warnings {
+            eprintln!("Warning: {warning}");
+        }
+        if !warnings.is_empty() {
+            eprintln!("Warning: suntime has no more accurate calculation to fall back on");
+        }
+    }
+    if args.strict {
+        for dt in &dates {
+            check_rollover(*dt, pos, clock)?;
+        }
+    }
+    let mut range = dates.into_iter();
+    if let Some(depression) = args.depression {
+        return depression_output(range, pos, depression, clock, args, out);
+    }
+    if !args.timezones.is_empty() {
+        return zones_output(range, pos, args, out);
+    }
+    if args.sparkline {
+        let lengths: Vec<_> = range
+            .map(|dt| SunTimes::from_pos(dt, pos, clock).day_length())
+            .collect();
+        writeln!(out, "{}", sparkline(&lengths))?;
+        return Ok(());
+    }
+    if args.summary {
+        let summary = match RangeSummary::new(range, pos, clock) {
+            Some(summary) => summary,
+            None => return Ok(()),
+        };
+        match args.format {
+            Format::Human => writeln!(
+                out,
+                "Longest day {} ({}), shortest day {} ({}), earliest sunrise {}, latest sunset {}",
+                summary.longest_day.date,
+                format_duration_hms(Duration::seconds(summary.longest_day.day_length_s)),
+                summary.shortest_day.date,
+                format_duration_hms(Duration::seconds(summary.shortest_day.day_length_s)),
+                time_extreme(&summary.earliest_sunrise),
+                time_extreme(&summary.latest_sunset),
+            )?,
+            Format::Json => writeln!(out, "{}", serde_json::to_string_pretty(&summary).unwrap())?,
+            _ => return Err("--summary only supports human and json output".into()),
+        }
+        return Ok(());
+    }
+    let sun_times =
+        |dt| SunTimes::from_pos(dt, pos, clock).with_twilight(args.twilight, dt, pos, clock);
+    match args.format {
+        Format::Human if args.almanac => {
+            range.try_for_each(|date| almanac_output(out, date, pos, clock, args.human_style()))?
+        }
+        Format::Human => {
+            range.try_for_each(|date| human_output(out, date, pos, clock, args.human_style()))?
+        }
+        Format::Csv => {
+            writeln!(out, "{}", csv_header(args))?;
+            range.try_for_each(|date| {
+                csv_output(
+                    out,
+                    date,
+                    pos,
+                    clock,
+                    args.twilight,
+                    args.moon,
+                    &args.date_format.0,
+                )
+            })?;
+        }
+        Format::Plot | Format::Svg => {
+            let output: Vec<_> = range.map(|dt| SunTimes::from_pos(dt, pos, clock)).collect();
+            let dates: Vec<_> = output.iter().map(|s| s.noon.naive_local().date()).collect();
+            let svg = args.format == Format::Svg;
+            if args.series == PlotSeries::DayLength {
+                let lengths: Vec<_> = output.iter().map(SunTimes::day_length).collect();
+                if svg {
+                    let svg = plot_durations_svg("Day length", &dates, &lengths, args.plot_style());
+                    write!(out, "{svg}")?;
+                } else {
+                    plot_durations(out, "Day length", &dates, &lengths, args.plot_style())?;
+                }
+            } else {
+                let sunrises: Vec<_> = output.iter().map(|s| s.sunrise).collect();
+                let sunsets: Vec<_> = output.iter().map(|s| s.sunset).collect();
+                let series = [("Sunrises", &sunrises[..]), ("Sunsets", &sunsets[..])];
+                if svg {
+                    write!(
+                        out,
+                        "{}",
+                        plot_times_svg("Sun", &dates, &series, args.plot_style())
+                    )?;
+                } else {
+                    plot_times_multi(out, "Sun", &dates, &series, args.plot_style())?;
+                }
+            }
+        }
+        Format::Json if args.epoch_ms => {
+            let output: Vec<_> = range
+                .map(|dt| WithMoon {
+                    times: SunTimesEpochMs::new(sun_times(dt)),
+                    moon: args.moon.then(|| MoonDay::new(dt, pos, clock)),
+                })
+                .collect();
+            writeln!(out, "{}", serde_json::to_string_pretty(&output).unwrap())?;
+        }
+        Format::Json => {
+            let output: Vec<_> = range
+                .map(|dt| WithMoon {
+                    times: sun_times(dt),
+                    moon: args.moon.then(|| MoonDay::new(dt, pos, clock)),
+                })
+                .collect();
+            writeln!(out, "{}", serde_json::to_string_pretty(&output).unwrap())?;
+        }
+        Format::Html | Format::Markdown => {
+            let rows: Vec<_> = range
+                .map(|dt| {
+                    let times = SunTimes::from_pos(dt, pos, clock);
+                    vec![
+                        dt.format("%Y-%m-%d").to_string(),
+                        times.sunrise.format("%H:%M:%S").to_string(),
+                        times.noon.format("%H:%M:%S").to_string(),
+                        times.sunset.format("%H:%M:%S").to_string(),
+                        format_duration_hms(times.day_length()),
+                    ]
+                })
+                .collect();
+            if args.format == Format::Markdown {
+                writeln!(out, "{}", markdown_table(&MARKDOWN_SUN_TIME_COLUMNS, &rows))?;
+                return Ok(());
+            }
+            let caption = match (rows.first(), rows.last()) {
+                (Some(first), Some(last)) if first[0] != last[0] => format!(
+                    "Sun times for {}, {} to {}",
+                    location_label(pos, args),
+                    first[0],
+                    last[0]
+                ),
+                (Some(first), _) => {
+                    format!("Sun times for {}, {}", location_label(pos, args), first[0])
+                }
+                _ => format!("Sun times for {}", location_label(pos, args)),
+            };
+            writeln!(out, "{}", html_table(&caption, &SUN_TIME_COLUMNS, &rows))?;
+        }
+        Format::Ics => {
+            let events = sun_events(range, pos);
+            write!(out, "{}", ics_calendar(&events, Utc::now(), args.reminder))?
+        }
+    }
+    Ok(())
+}
+
+/// Sunrise and sunset on each day as calendar events, identified by date and kind
+fn sun_events(range: impl Iterator<Item = DateTime<Utc>>, pos: Pos) -> Vec<IcsEvent> {
+    range
+        .flat_map(|dt| {
+            let date = dt.format("%Y%m%d");
+            let noon = pos.solar_noon(dt);
+            [
+                ("Sunrise", pos.try_sunrise(noon)),
+                ("Sunset", pos.try_sunset(noon)),
+            ]
+            .into_iter()
+            .filter_map(move |(kind, time)| {
+                Some(IcsEvent {
+                    uid: format!("{date}-{}@suntime", kind.to_lowercase()),
+                    summary: kind.to_string(),
+                    start: time.ok()?,
+                })
+            })
+        })
+        .collect()
+}
+
+const CALENDAR_CELL_WIDTH: usize = 6;
+
+/// Lays out consecutive days as a grid of weeks, Monday first, with each cell showing the day of
+/// the month over its sunrise and sunset. Cells before the first day are left blank.
+fn render_calendar(days: &[(NaiveDate, SunTimes)]) -> Vec<String> {
+    let first = match days.first() {
+        Some((first, _)) => first,
+        None => return vec![],
+    };
+    let leading = first.weekday().num_days_from_monday() as usize;
+    let cells: Vec<_> = std::iter::repeat_with(|| None)
+        .take(leading)
+        .chain(days.iter().map(Some))
+        .collect();
+
+    let mut rows = vec![["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"]
+        .map(|day| format!("{day:<CALENDAR_CELL_WIDTH$}"))
+        .join(" ")
+        .trim_end()
+        .to_string()];
+    for week in cells.chunks(7) {
+        let line = |cell: &dyn Fn(&(NaiveDate, SunTimes)) -> String| {
+            week.iter()
+                .map(|day| format!("{:<CALENDAR_CELL_WIDTH$}", day.map_or(String::new(), cell)))
+                .collect::<Vec<_>>()
+                .join(" ")
+                .trim_end()
+                .to_string()
+        };
+        rows.push(line(&|(date, _)| format!("{:>2}", date.day())));
+        rows.push(line(&|(_, times)| {
+            times.sunrise.format("↑%H:%M").to_string()
+        }));
+        rows.push(line(&|(_, times)| {
+            times.sunset.format("↓%H:%M").to_string()
+        }));
+    }
+    rows
+}
+
+#[test]
+fn test_render_calendar() {
+    let pos = Pos::new(45., -75.);
+    // March 2023 starts on a Wednesday
+    let start = NaiveDate::from_ymd_opt(2023, 3, 1).unwrap();
+    let end = NaiveDate::from_ymd_opt(2023, 3, 31).unwrap();
+    let days: Vec<_> = noons(start..=end)
+        .map(|dt| {
+            let times = SunTimes::from_pos(dt, pos, Clock::TrueSolar);
+            (dt.naive_utc().date(), times)
+        })
+        .collect();
+    let rows = render_calendar(&days);
+    // Header plus three lines for each of five weeks
+    assert_eq!(rows.len(), 1 + 3 * 5);
+    assert!(rows[0].starts_with("Mon    Tue    Wed"));
+    assert_eq!(rows[1], format!("{:14} 1      2      3      4      5", ""));
+    assert!(rows[2].starts_with(&format!("{:14}↑", "")));
+    assert_eq!(rows[13].trim_end(), "27     28     29     30     31");
+}
+
+/// Errors if any of the day's events, as displayed, falls on a different calendar day than the
+/// one requested
+fn check_rollover(dt: DateTime<Utc>, pos: Pos, clock: Clock) -> Result<(), Box<dyn Error>> {
+    let times = SunTimes::from_pos(dt, pos, clock);
+    let requested = dt.with_timezone(times.noon.offset()).naive_local().date();
+    for (event, time) in [
+        ("Sunrise", times.sunrise),
+        ("Solar noon", times.noon),
+        ("Sunset", times.sunset),
+    ] {
+        let actual = time.naive_local().date();
+        if actual != requested {
+            return Err(format!(
+                "{event} for {requested} falls on {actual} ({})",
+                time.to_rfc3339()
+            )
+            .into());
+        }
+    }
+    Ok(())
+}
+
+const SUN_TIME_COLUMNS: [(&str, &str); 5] = [
+    ("date", "Date"),
+    ("sunrise", "Sunrise"),
+    ("noon", "Solar noon"),
+    ("sunset", "Sunset"),
+    ("day-length", "Day length"),
+];
+
+const MARKDOWN_SUN_TIME_COLUMNS: [(&str, &str); 5] = [
+    ("date", "Date"),
+    ("sunrise", "Sunrise"),
+    ("noon", "Noon"),
+    ("sunset", "Sunset"),
+    ("day-length", "Day length"),
+];
+
+/// Names the location as the user gave it: the city if there was one, otherwise coordinates
+fn location_label(pos: Pos, args: &Args) -> String {
+    args.city
+        .first()
+        .cloned()
+        .unwrap_or_else(|| pos.to_string())
+}
+
+/// Noon UTC on each day of `range`, the instant each day's times are worked out from
+fn noons(range: RangeInclusive<NaiveDate>) -> impl Iterator<Item = DateTime<Utc>> {
+    suntime::days(range).map(|date| {
+        Utc.from_utc_datetime(&date.and_hms_opt(12, 0, 0).expect("Noon is a valid time"))
+    })
+}
+
+fn format_duration_ms(duration: Duration) -> String {
+    format!(
+        "{}{}:{:02}",
+        if duration.num_seconds() < 0 { "-" } else { "" },
+        duration.num_minutes().abs(),
+        (duration.num_seconds() % 60).abs()
+    )
+}
+
+fn format_duration_hms(duration: Duration) -> String {
+    let sign = if duration < Duration::zero() { "-" } else { "" };
+    let seconds = duration.num_seconds().abs();
+    format!(
+        "{sign}{}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+#[test]
+fn test_format_duration_hms() {
+    assert_eq!(format_duration_hms(Duration::seconds(3723)), "1:02:03");
+    assert_eq!(format_duration_hms(Duration::seconds(-3723)), "-1:02:03");
+    assert_eq!(format_duration_hms(Duration::seconds(-59)), "-0:00:59");
+    assert_eq!(format_duration_hms(Duration::zero()), "0:00:00");
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct SunTimes {
+    #[serde(serialize_with = "serialize_dt", deserialize_with = "deserialize_dt")]
+    sunrise: DateTime<FixedOffset>,
+    #[serde(serialize_with = "serialize_dt", deserialize_with = "deserialize_dt")]
+    noon: DateTime<FixedOffset>,
+    #[serde(serialize_with = "serialize_dt", deserialize_with = "deserialize_dt")]
+    sunset: DateTime<FixedOffset>,
+    /// Only there with --twilight. Its fields are required when deserializing, so JSON without
+    /// them leaves this as `None` rather than a set of empty twilights.
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    twilight: Option<TwilightTimes>,
+}
+
+/// Dawn and dusk at each standard twilight, or `None` when the sun doesn't reach its depression.
+/// These are serialized as null rather than left out, so every row has the same fields.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+struct TwilightTimes {
+    #[serde(
+        serialize_with = "serialize_opt_dt",
+        deserialize_with = "deserialize_opt_dt"
+    )]
+    civil_dawn: Option<DateTime<FixedOffset>>,
+    #[serde(
+        serialize_with = "serialize_opt_dt",
+        deserialize_with = "deserialize_opt_dt"
+    )]
+    civil_dusk: Option<DateTime<FixedOffset>>,
+    #[serde(
+        serialize_with = "serialize_opt_dt",
+        deserialize_with = "deserialize_opt_dt"
+    )]
+    nautical_dawn: Option<DateTime<FixedOffset>>,
+    #[serde(
+        serialize_with = "serialize_opt_dt",
+        deserialize_with = "deserialize_opt_dt"
+    )]
+    nautical_dusk: Option<DateTime<FixedOffset>>,
+    #[serde(
+        serialize_with = "serialize_opt_dt",
+        deserialize_with = "deserialize_opt_dt"
+    )]
+    astronomical_dawn: Option<DateTime<FixedOffset>>,
+    #[serde(
+        serialize_with = "serialize_opt_dt",
+        deserialize_with = "deserialize_opt_dt"
+    )]
+    astronomical_dusk: Option<DateTime<FixedOffset>>,
+}
+
+impl TwilightTimes {
+    fn new(dt: DateTime<Utc>, pos: Pos, clock: Clock) -> Self {
+        let tz = clock.offset(pos, dt);
+        let local = |time: Option<DateTime<Utc>>| time.map(|time| time.with_timezone(&tz));
+        TwilightTimes {
+            civil_dawn: local(pos.civil_dawn(dt)),
+            civil_dusk: local(pos.civil_dusk(dt)),
+            nautical_dawn: local(pos.nautical_dawn(dt)),
+            nautical_dusk: local(pos.nautical_dusk(dt)),
+            astronomical_dawn: local(pos.astronomical_dawn(dt)),
+            astronomical_dusk: local(pos.astronomical_dusk(dt)),
+        }
+    }
+
+    /// The times in the order of their CSV columns
+    fn times(&self) -> [Option<DateTime<FixedOffset>>; 6] {
+        [
+            self.civil_dawn,
+            self.civil_dusk,
+            self.nautical_dawn,
+            self.nautical_dusk,
+            self.astronomical_dawn,
+            self.astronomical_dusk,
+        ]
+    }
+
+    fn map(self, f: impl Fn(DateTime<FixedOffset>) -> DateTime<FixedOffset>) -> Self {
+        TwilightTimes {
+            civil_dawn: self.civil_dawn.map(&f),
+            civil_dusk: self.civil_dusk.map(&f),
+            nautical_dawn: self.nautical_dawn.map(&f),
+            nautical_dusk: self.nautical_dusk.map(&f),
+            astronomical_dawn: self.astronomical_dawn.map(&f),
+            astronomical_dusk: self.astronomical_dusk.map(&f),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct DayLengthExtreme {
+    date: String,
+    day_length_s: i64,
+}
+
+#[derive(Serialize)]
+struct TimeExtreme {
+    date: String,
+    #[serde(serialize_with = "serialize_dt")]
+    time: DateTime<FixedOffset>,
+}
+
+/// The earliest and latest sunrise and sunset of a year, as printed by the extremes command
+#[derive(Serialize)]
+struct YearExtremes {
+    earliest_sunrise: TimeExtreme,
+    latest_sunrise: TimeExtreme,
+    earliest_sunset: TimeExtreme,
+    latest_sunset: TimeExtreme,
+}
+
+impl YearExtremes {
+    /// Compares sunrises and sunsets by their time of day on `clock`, leaving out days the sun
+    /// doesn't rise and set. `None` if it doesn't on any day of `year`.
+    fn new(year: i32, pos: Pos, clock: Clock) -> Option<Self> {
+        let start = NaiveDate::from_ymd_opt(year, 1, 1)?;
+        let end = NaiveDate::from_ymd_opt(year, 12, 31)?;
+        let local = |time: DateTime<Utc>| time.with_timezone(&clock.offset(pos, time));
+        let days: Vec<_> = pos
+            .events_over(start..=end)
+            .filter(|(_, events)| {
+                let length = events.day_length();
+                length > Duration::zero() && length < Duration::days(1)
+            })
+            .map(|(date, events)| (date, local(events.sunrise), local(events.sunset)))
+            .collect();
+        let extreme = |(date, time): (NaiveDate, DateTime<FixedOffset>)| TimeExtreme {
+            date: date.format("%Y-%m-%d").to_string(),
+            time,
+        };
+        let sunrises = || days.iter().map(|(date, sunrise, _)| (*date, *sunrise));
+        let sunsets = || days.iter().map(|(date, _, sunset)| (*date, *sunset));
+        Some(YearExtremes {
+            earliest_sunrise: extreme(sunrises().min_by_key(|(_, time)| time.time())?),
+            latest_sunrise: extreme(sunrises().max_by_key(|(_, time)| time.time())?),
+            earliest_sunset: extreme(sunsets().min_by_key(|(_, time)| time.time())?),
+            latest_sunset: extreme(sunsets().max_by_key(|(_, time)| time.time())?),
+        })
+    }
+}
+
+fn output_extremes(year: i32, pos: Pos, args: &Args) -> Result<(), Box<dyn Error>> {
+    let extremes = YearExtremes::new(year, pos, args.clock(pos))
+        .ok_or_else(|| format!("The sun doesn't rise and set here in {year}"))?;
+    let rows = [
+        (
+            "Earliest sunrise",
+            "earliest_sunrise",
+            &extremes.earliest_sunrise,
+        ),
+        ("Latest sunrise", "latest_sunrise", &extremes.latest_sunrise),
+        (
+            "Earliest sunset",
+            "earliest_sunset",
+            &extremes.earliest_sunset,
+        ),
+        ("Latest sunset", "latest_sunset", &extremes.latest_sunset),
+    ];
+    let mut out = args.writer()?;
+    match args.format {
+        Format::Human => {
+            for (label, _, extreme) in rows {
+                let time = extreme.time.format("%H:%M:%S");
+                writeln!(out, "{label:<16} {} {time}", extreme.date)?;
+            }
+        }
+        Format::Csv => {
+            // Like the daily CSV, times are seconds since local midnight
+            writeln!(out, "extreme,date,time_s")?;
+            for (_, name, extreme) in rows {
+                let seconds = extreme.time.num_seconds_from_midnight();
+                writeln!(out, "{name},{},{seconds}", extreme.date)?;
+            }
+        }
+        Format::Json => writeln!(out, "{}", serde_json::to_string_pretty(&extremes).unwrap())?,
+        _ => return Err("The extremes command only supports human, csv and json output".into()),
+    }
+    out.flush()?;
+    Ok(())
+}
+
+#[test]
+fn test_year_extremes() {
+    let london = Pos::new(51.5, -0.13);
+    let clock = Clock::Zone(ZoneArg::Named(chrono_tz::Europe::London));
+    let extremes = YearExtremes::new(2023, london, clock).unwrap();
+    let day_of = |extreme: &TimeExtreme| extreme.time.naive_local().date();
+    let date = |m, d| NaiveDate::from_ymd_opt(2023, m, d).unwrap();
+    // Neither the earliest sunset nor the latest sunrise is on the winter solstice, but either
+    // side of it
+    let earliest_sunset = day_of(&extremes.earliest_sunset);
+    assert!(
+        (date(12, 8)..=date(12, 16)).contains(&earliest_sunset),
+        "{earliest_sunset}"
+    );
+    let latest_sunrise = day_of(&extremes.latest_sunrise);
+    assert!(
+        latest_sunrise >= date(12, 26) || latest_sunrise <= date(1, 4),
+        "{latest_sunrise}"
+    );
+    let earliest_sunrise = day_of(&extremes.earliest_sunrise);
+    assert!(
+        (date(6, 12)..=date(6, 20)).contains(&earliest_sunrise),
+        "{earliest_sunrise}"
+    );
+    let latest_sunset = day_of(&extremes.latest_sunset);
+    assert!(
+        (date(6, 22)..=date(6, 30)).contains(&latest_sunset),
+        "{latest_sunset}"
+    );
+    assert_eq!(extremes.earliest_sunset.date, earliest_sunset.to_string());
+}
+
+/// The extremes of a range of days, as printed by --summary. Sunrises and sunsets are compared
+/// by their time of day on the clock being shown, leaving out days the sun doesn't rise and set,
+/// so they're `None` if it doesn't on any day of the range.
+#[derive(Serialize)]
+struct RangeSummary {
+    longest_day: DayLengthExtreme,
+    shortest_day: DayLengthExtreme,
+    earliest_sunrise: Option<TimeExtreme>,
+    latest_sunset: Option<TimeExtreme>,
+}
+
+/// A sunrise or sunset extreme as "date time", or "none" if there wasn't one
+fn time_extreme(extreme: &Option<TimeExtreme>) -> String {
+    extreme.as_ref().map_or("none".to_string(), |extreme| {
+        format!("{} {}", extreme.date, extreme.time.format("%H:%M:%S"))
+    })
+}
+
+impl RangeSummary {
+    fn new(range: impl Iterator<Item = DateTime<Utc>>, pos: Pos, clock: Clock) -> Option<Self> {
+        let days: Vec<_> = range
+            .map(|dt| {
+                let rises_and_sets = pos.try_sunrise(dt).is_ok() && pos.try_sunset(dt).is_ok();
+                (SunTimes::from_pos(dt, pos, clock), rises_and_sets)
+            })
+            .collect();
+        let date = |times: &SunTimes| times.noon.format("%Y-%m-%d").to_string();
+        let day_length = |times: &SunTimes| DayLengthExtreme {
+            date: date(times),
+            day_length_s: times.day_length().num_seconds(),
+        };
+        let all = || days.iter().map(|(times, _)| times);
+        let rising = || {
+            days.iter()
+                .filter(|(_, rises_and_sets)| *rises_and_sets)
+                .map(|(times, _)| times)
+        };
+        Some(RangeSummary {
+            longest_day: day_length(all().max_by_key(|times| times.day_length())?),
+            shortest_day: day_length(all().min_by_key(|times| times.day_length())?),
+            earliest_sunrise: rising()
+                .min_by_key(|times| times.sunrise.time())
+                .map(|times| TimeExtreme {
+                    date: date(times),
+                    time: times.sunrise,
+                }),
+            latest_sunset: rising()
+                .max_by_key(|times| times.sunset.time())
+                .map(|times| TimeExtreme {
+                    date: date(times),
+                    time: times.sunset,
+                }),
+        })
+    }
+}
+
+fn serialize_dt<S>(value: &DateTime<FixedOffset>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    value.to_rfc3339().serialize(serializer)
+}
+
+fn deserialize_dt<'de, D>(deserializer: D) -> Result<DateTime<FixedOffset>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = String::deserialize(deserializer)?;
+    DateTime::parse_from_rfc3339(&value).map_err(serde::de::Error::custom)
+}
+
+fn serialize_opt_dt<S>(
+    value: &Option<DateTime<FixedOffset>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    value.map(|dt| dt.to_rfc3339()).serialize(serializer)
+}
+
+fn deserialize_opt_dt<'de, D>(deserializer: D) -> Result<Option<DateTime<FixedOffset>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Option::<String>::deserialize(deserializer)?
+        .map(|value| DateTime::parse_from_rfc3339(&value).map_err(serde::de::Error::custom))
+        .transpose()
+}
+
+#[test]
+fn test_sun_times_round_trip() {
+    let dt = Utc.with_ymd_and_hms(2023, 3, 20, 12, 0, 0).unwrap();
+    let zone = ZoneArg::Fixed(FixedOffset::east_opt(5 * 3600 + 1800).unwrap());
+    let times = SunTimes::from_pos(dt, Pos::new(28.6, 77.2), Clock::Zone(zone));
+    let json = serde_json::to_string(&times).unwrap();
+    assert!(json.contains("+05:30"), "{json}");
+    assert_eq!(serde_json::from_str::<SunTimes>(&json).unwrap(), times);
+    assert!(
+        serde_json::from_str::<SunTimes>(r#"{"sunrise":"dawn","noon":"","sunset":""}"#).is_err()
+    );
+}
+
+#[test]
+fn test_twilight_output() {
+    // London never gets astronomically dark around midsummer
+    let pos = Pos::new(51.5, 0.);
+    let clock = Clock::Zone(ZoneArg::Fixed(FixedOffset::east_opt(3600).unwrap()));
+    let dt = Utc.with_ymd_and_hms(2023, 6, 21, 12, 0, 0).unwrap();
+    let times = SunTimes::from_pos(dt, pos, clock).with_twilight(true, dt, pos, clock);
+    let twilight = times.twilight.unwrap();
+    assert!(twilight.civil_dawn.unwrap() < times.sunrise);
+    assert!(twilight.nautical_dusk.unwrap() > twilight.civil_dusk.unwrap());
+    assert_eq!(twilight.astronomical_dawn, None);
+
+    let json = serde_json::to_string(&times).unwrap();
+    assert!(json.contains(r#""astronomical_dawn":null"#), "{json}");
+    assert!(json.contains(r#""civil_dusk":"2023-06-21T22:"#), "{json}");
+    assert_eq!(serde_json::from_str::<SunTimes>(&json).unwrap(), times);
+    let json = serde_json::to_string(&SunTimes::from_pos(dt, pos, clock)).unwrap();
+    assert!(!json.contains("civil_dawn"), "{json}");
+
+    let args = Args::parse_from(["suntime", "--twilight", "--moon"]);
+    let header = csv_header(&args);
+    let mut row = Vec::new();
+    csv_output(&mut row, dt, pos, clock, true, true, DEFAULT_DATE_FORMAT).unwrap();
+    let row = String::from_utf8(row).unwrap();
+    let fields: Vec<_> = row.trim_end().split(',').collect();
+    let columns: Vec<_> = header.split(',').collect();
+    assert_eq!(fields.len(), columns.len(), "{header}\n{row}");
+    let field = |name| fields[columns.iter().position(|column| *column == name).unwrap()];
+    assert_eq!(field("astronomical_dusk_s"), "");
+    let civil_dawn = field("civil_dawn_s").parse::<i64>().unwrap();
+    assert!(
+        civil_dawn < field("sunrise_s").parse::<i64>().unwrap(),
+        "{row}"
+    );
+    assert!(columns.ends_with(&["moonrise_s", "moonset_s"]));
+}
+
+#[test]
+fn test_human_row_markers() {
+    let style = |markers, day_length| HumanStyle {
+        markers,
+        day_length,
+        ..HumanStyle::default()
+    };
+    let pos = Pos::new(51.5, 0.);
+    let clock = Clock::Zone(ZoneArg::Fixed(FixedOffset::east_opt(0).unwrap()));
+    let dt = Utc.with_ymd_and_hms(2023, 3, 20, 12, 0, 0).unwrap();
+    assert!(human_row(dt, pos, clock, style(Markers::Emoji, false)).starts_with("2023-03-20 🌅 "));
+    let ascii = human_row(dt, pos, clock, style(Markers::Ascii, false));
+    assert!(ascii.starts_with("2023-03-20 rise "), "{ascii}");
+    assert!(!ascii.contains(['🌅', '🌞', '🌇']), "{ascii}");
+    // The delta columns line up from one day to the next
+    let deltas = |row: String| row.match_indices('Δ').map(|(i, _)| i).collect::<Vec<_>>();
+    let next = human_row(
+        dt + Duration::days(1),
+        pos,
+        clock,
+        style(Markers::Ascii, false),
+    );
+    assert_eq!(deltas(ascii), deltas(next));
+}
+
+#[test]
+fn test_compare_locations() {
+    let args = Args::parse_from([
+        "suntime",
+        "--lat=51.5",
+        "--long=0",
+        "--lat=48.9",
+        "--long=2.35",
+        "--utc",
+        "--format=json",
+    ]);
+    let positions = validate_location(&args).unwrap();
+    assert_eq!(positions.len(), 2);
+    let date = NaiveDate::from_ymd_opt(2023, 3, 20).unwrap();
+    let mut out = vec![];
+    compare_output(
+        noons(date..=date),
+        (positions[0], positions[1]),
+        &args,
+        &mut out,
+    )
+    .unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&out).unwrap();
+    let day = &json[0];
+    assert_eq!(day["date"], "2023-03-20");
+    assert_eq!(day["locations"][1]["location"], positions[1].to_string());
+    assert!(day["locations"][0]["sunrise"].is_string(), "{day}");
+    // Paris is east of London, so on a shared clock the sun rises there first
+    let delta = day["sunrise_delta_s"].as_i64().unwrap();
+    assert!((-15 * 60..-5 * 60).contains(&delta), "{delta}");
+
+    // CSV has the single-location columns for each, and human output the chosen formats
+    let compare = |format: &str| {
+        let args = Args::parse_from([
+            "suntime",
+            "--lat=51.5",
+            "--long=0",
+            "--lat=48.9",
+            "--long=2.35",
+            "--utc",
+            "--date-format=%d/%m",
+            "--time-format=%H:%M",
+            format,
+        ]);
+        let mut out = vec![];
+        compare_output(
+            noons(date..=date),
+            (positions[0], positions[1]),
+            &args,
+            &mut out,
+        )
+        .unwrap();
+        String::from_utf8(out).unwrap()
+    };
+    let csv = compare("--format=csv");
+    let lines: Vec<_> = csv.lines().collect();
+    assert_eq!(
+        lines[0],
+        "date,sunrise_s_1,noon_s_1,sunset_s_1,day_length_s_1,\
+         sunrise_s_2,noon_s_2,sunset_s_2,day_length_s_2,sunrise_delta_s"
+    );
+    let fields: Vec<_> = lines[1].split(',').collect();
+    assert_eq!(fields.len(), 10, "{csv}");
+    assert_eq!(fields[0], "20/03");
+    let sunrise = |field: &str| field.parse::<i64>().unwrap();
+    assert_eq!(sunrise(fields[5]) - sunrise(fields[1]), delta);
+    let human = compare("--format=human");
+    assert!(human.starts_with("20/03 "), "{human}");
+    let dt = noons(date..=date).next().unwrap();
+    let sunrise = SunTimes::from_pos(dt, positions[0], args.clock(positions[0])).sunrise;
+    assert!(
+        human.contains(&format!(" {} ", sunrise.format("%H:%M"))),
+        "{human}"
+    );
+
+    // Each in its own zone, the clocks are an hour apart but the sunrises aren't
+    let args = Args::parse_from([
+        "suntime",
+        "--lat=51.5",
+        "--long=0",
+        "--lat=48.9",
+        "--long=2.35",
+        "--format=json",
+    ]);
+    let mut out = vec![];
+    compare_output(
+        noons(date..=date),
+        (positions[0], positions[1]),
+        &args,
+        &mut out,
+    )
+    .unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&out).unwrap();
+    assert_eq!(json[0]["sunrise_delta_s"].as_i64(), Some(delta));
+    let args = Args::parse_from([
+        "suntime",
+        "--lat=51.5",
+        "--long=0",
+        "--lat=48.9",
+        "--long=2.35",
+        "--format=human",
+    ]);
+    let mut out = vec![];
+    compare_output(
+        noons(date..=date),
+        (positions[0], positions[1]),
+        &args,
+        &mut out,
+    )
+    .unwrap();
+    let human = String::from_utf8(out).unwrap();
+    assert!(
+        human.ends_with(&format!(" -0:{:02}:{:02}\n", -delta / 60, -delta % 60)),
+        "{human}"
+    );
+
+    // Sunrises either side of midnight UTC are still minutes apart, not most of a day: 92°E
+    // sees the sun rise four minutes before 91°E
+    let (west, east) = (Pos::new(0., 91.), Pos::new(0., 92.));
+    let args = Args::parse_from(["suntime", "--utc", "--format=json"]);
+    let mut out = vec![];
+    compare_output(noons(date..=date), (west, east), &args, &mut out).unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&out).unwrap();
+    let times = &json[0]["locations"];
+    assert_ne!(
+        times[0]["sunrise"].as_str().unwrap()[..10],
+        times[1]["sunrise"].as_str().unwrap()[..10]
+    );
+    let delta = json[0]["sunrise_delta_s"].as_i64().unwrap();
+    assert!((-5 * 60..-3 * 60).contains(&delta), "{delta}");
+
+    let args = Args::parse_from([
+        "suntime", "--lat=1", "--long=1", "--lat=2", "--long=2", "--lat=3", "--long=3",
+    ]);
+    assert!(matches!(
+        validate_location(&args),
+        Err(LocationError::TooManyLocations(3))
+    ));
+}
+
+#[test]
+fn test_output_to_file() {
+    let path = std::env::temp_dir().join(format!("suntime-output-{}.csv", std::process::id()));
+    let output = path.to_str().unwrap();
+    let args = Args::parse_from([
+        "suntime",
+        "--lat=51.5",
+        "--long=0",
+        "--utc",
+        "--format=csv",
+        "--output",
+        output,
+    ]);
+    let date = NaiveDate::from_ymd_opt(2023, 3, 20).unwrap();
+    let result = output_range(
+        noons(date..=date + Duration::days(1)),
+        &[Pos::new(51.5, 0.)],
+        &args,
+    );
+    let written = std::fs::read_to_string(&path);
+    std::fs::remove_file(&path).unwrap();
+    result.unwrap();
+    let lines: Vec<_> = written.unwrap().lines().map(str::to_string).collect();
+    assert_eq!(lines.len(), 3, "{lines:?}");
+    assert_eq!(lines[0], CSV_HEADER);
+    assert!(lines[1].starts_with("2023-03-20,"), "{}", lines[1]);
+
+    let args = Args::parse_from(["suntime", "--output", "/nonexistent/dir/out.csv"]);
+    assert!(output_range(noons(date..=date), &[Pos::new(51.5, 0.)], &args).is_err());
+}
+
+#[test]
+fn test_human_row_color() {
+    let pos = Pos::new(51.5, 0.);
+    let clock = Clock::Zone(ZoneArg::Fixed(FixedOffset::east_opt(0).unwrap()));
+    // Days are lengthening in March: sunrise earlier, sunset later
+    let dt = Utc.with_ymd_and_hms(2023, 3, 20, 12, 0, 0).unwrap();
+    let style = HumanStyle {
+        markers: Markers::Ascii,
+        color: true,
+        ..HumanStyle::default()
+    };
+    let row = human_row(dt, pos, clock, style);
+    let plain = human_row(
+        dt,
+        pos,
+        clock,
+        HumanStyle {
+            color: false,
+            ..style
+        },
+    );
+    assert!(row.contains(&format!("rise {YELLOW}")), "{row}");
+    assert!(row.contains(&format!("set {RED}")), "{row}");
+    assert!(row.contains(&format!("(Δ{RED}")), "{row}");
+    assert!(row.contains(&format!("Δ{GREEN}")), "{row}");
+    assert!(!plain.contains('\x1b'), "{plain}");
+    // Without the escape codes, it's the same line
+    let stripped = [YELLOW, RED, GREEN, RESET_COLOR]
+        .iter()
+        .fold(row, |row, code| row.replace(code, ""));
+    assert_eq!(stripped, plain);
+}
+
+#[test]
+fn test_human_row_moon() {
+    let pos = Pos::new(51.5, 0.);
+    let clock = Clock::Zone(ZoneArg::Fixed(FixedOffset::east_opt(0).unwrap()));
+    let dt = Utc.with_ymd_and_hms(2023, 8, 31, 12, 0, 0).unwrap();
+    let style = HumanStyle {
+        markers: Markers::Ascii,
+        day_length: true,
+        moon: true,
+        ..HumanStyle::default()
+    };
+    let row = human_row(dt, pos, clock, style);
+    let (rest, moon) = row.split_once(" moon ").unwrap();
+    assert!(rest.contains(" Day: "), "{row}");
+    assert!(moon.starts_with("Full Moon "), "{row}");
+    // It sets early that morning and rises in the evening
+    assert!(
+        moon.contains("% rise 19:") && moon.contains(" set 05:"),
+        "{row}"
+    );
+    let emoji = human_row(
+        dt,
+        pos,
+        clock,
+        HumanStyle {
+            markers: Markers::Emoji,
+            ..style
+        },
+    );
+    assert!(emoji.contains(" 🌕 Full Moon "), "{emoji}");
+}
+
+#[test]
+fn test_human_row_day_length() {
+    let style = |markers, day_length| HumanStyle {
+        markers,
+        day_length,
+        ..HumanStyle::default()
+    };
+    let pos = Pos::new(51.5, 0.);
+    let clock = Clock::Zone(ZoneArg::Fixed(FixedOffset::east_opt(0).unwrap()));
+    let dt = Utc.with_ymd_and_hms(2023, 12, 21, 12, 0, 0).unwrap();
+    let row = human_row(dt, pos, clock, style(Markers::Ascii, false));
+    assert!(!row.contains("Day:"), "{row}");
+    // Under ten hours, the length is padded to line up with longer days
+    let with_length = human_row(dt, pos, clock, style(Markers::Ascii, true));
+    let suffix = with_length.strip_prefix(&row).unwrap();
+    assert!(suffix.starts_with(" Day:  7:"), "{suffix}");
+    assert_eq!(suffix.len(), " Day: ".len() + 8);
+}
+
+#[test]
+fn test_human_row_relative() {
+    let pos = Pos::new(51.5, 0.);
+    let clock = Clock::Zone(ZoneArg::Fixed(FixedOffset::east_opt(0).unwrap()));
+    let today = NaiveDate::from_ymd_opt(2023, 3, 20).unwrap();
+    let style = HumanStyle {
+        markers: Markers::Ascii,
+        relative_to: Some(today),
+        ..HumanStyle::default()
+    };
+    let rows: Vec<_> = noons(today - Duration::days(2)..=today + Duration::days(2))
+        .map(|dt| human_row(dt, pos, clock, style))
+        .collect();
+    assert!(rows[0].starts_with("2023-03-18 rise"), "{}", rows[0]);
+    assert!(rows[1].starts_with("Yesterday  rise"), "{}", rows[1]);
+    assert!(rows[2].starts_with("Today      rise"), "{}", rows[2]);
+    assert!(rows[3].starts_with("Tomorrow   rise"), "{}", rows[3]);
+    assert!(rows[4].starts_with("2023-03-22 rise"), "{}", rows[4]);
+    // The labels don't move the times
+    let column = |row: &String| row.find(" rise ").unwrap();
+    assert!(rows.iter().all(|row| column(row) == column(&rows[0])));
+}
+
+#[test]
+fn test_time_formats() {
+    assert!("%I:%M %p".parse::<TimeFormat>().is_ok());
+    assert!(matches!(
+        "%H:%Q".parse::<TimeFormat>(),
+        Err(LocationError::BadTimeFormat(_))
+    ));
+    assert!(Args::try_parse_from(["suntime", "--time-format", "%"]).is_err());
+
+    let args = Args::parse_from([
+        "suntime",
+        "--no-emoji",
+        "--color",
+        "never",
+        "--date-format",
+        "%a %b %d",
+        "--time-format",
+        "%I:%M %p",
+    ]);
+    let pos = Pos::new(51.5, 0.);
+    let clock = Clock::Zone(ZoneArg::Fixed(FixedOffset::east_opt(0).unwrap()));
+    let dt = Utc.with_ymd_and_hms(2023, 3, 20, 12, 0, 0).unwrap();
+    let row = human_row(dt, pos, clock, args.human_style());
+    assert!(row.starts_with("Mon Mar 20 rise 06:"), "{row}");
+    assert!(row.contains(" AM (Δ") && row.contains(" PM (Δ"), "{row}");
+    let csv = csv_row(dt, pos, clock, &args.date_format.0);
+    assert!(csv.starts_with("Mon Mar 20,"), "{csv}");
+}
+
+#[test]
+fn test_human_row_shadow() {
+    let style = HumanStyle {
+        markers: Markers::Ascii,
+        shadow: true,
+        ..HumanStyle::default()
+    };
+    let clock = Clock::Zone(ZoneArg::Fixed(FixedOffset::east_opt(0).unwrap()));
+    let dt = Utc.with_ymd_and_hms(2023, 3, 20, 12, 0, 0).unwrap();
+    // Around the equinox the noon sun is about 45° up at 45°N, casting shadows about as long as
+    // the object is tall
+    let pos = Pos::new(45., 0.);
+    let expected = pos.shadow_ratio(pos.solar_noon(dt)).unwrap();
+    let row = human_row(dt, pos, clock, style);
+    let (_, ratio) = row.rsplit_once(" Shadow: ").unwrap();
+    assert_eq!(ratio, format!("{expected:>6.2}"), "{row}");
+    // No noon sun in polar night
+    let dt = Utc.with_ymd_and_hms(2023, 12, 21, 12, 0, 0).unwrap();
+    let row = human_row(dt, Pos::new(80., 0.), clock, style);
+    assert!(row.ends_with(" Shadow:     --"), "{row}");
+}
+
+#[test]
+fn test_offset_per_date() {
+    // New York springs forward on 2023-03-12
+    let pos = Pos::new(40.7, -74.0);
+    let before = Utc.with_ymd_and_hms(2023, 3, 11, 12, 0, 0).unwrap();
+    let after = Utc.with_ymd_and_hms(2023, 3, 13, 12, 0, 0).unwrap();
+    let clock = Clock::Zone(ZoneArg::Named(chrono_tz::America::New_York));
+    let hours = |dt| {
+        SunTimes::from_pos(dt, pos, clock)
+            .noon
+            .offset()
+            .local_minus_utc()
+            / 3600
+    };
+    assert_eq!(hours(before), -5);
+    assert_eq!(hours(after), -4);
+
+    // The machine's zone is looked up on each date too, not once for today
+    for dt in [before, after] {
+        let expected = Local.offset_from_utc_datetime(&dt.naive_utc()).fix();
+        assert_eq!(
+            *SunTimes::from_pos(dt, pos, Clock::Local).noon.offset(),
+            expected
+        );
+    }
+}
+
+#[test]
+fn test_parse_zone_arg() {
+    let fixed = |seconds| ZoneArg::Fixed(FixedOffset::east_opt(seconds).unwrap());
+    assert_eq!("+05:30".parse::<ZoneArg>().unwrap(), fixed(5 * 3600 + 1800));
+    assert_eq!("-08".parse::<ZoneArg>().unwrap(), fixed(-8 * 3600));
+    assert_eq!(
+        "Asia/Tokyo".parse::<ZoneArg>().unwrap(),
+        ZoneArg::Named(chrono_tz::Asia::Tokyo)
+    );
+    assert!("+05:75".parse::<ZoneArg>().is_err());
+    assert!("+25:00".parse::<ZoneArg>().is_err());
+    assert!("Mars/Olympus_Mons".parse::<ZoneArg>().is_err());
+}
+
+/// How event instants are shown as times of day
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Clock {
+    /// The machine's local time zone
+    Local,
+    /// Apparent solar time at the location, as a sundial would read it
+    TrueSolar,
+    /// A zone given with --tz or --utc
+    Zone(ZoneArg),
+}
+
+impl Clock {
+    /// Offset to display events on the day of `dt` at `pos` with
+    fn offset(self, pos: Pos, dt: DateTime<Utc>) -> FixedOffset {
+        match self {
+            Clock::Local => Local.offset_from_utc_datetime(&dt.naive_utc()).fix(),
+            Clock::TrueSolar => {
+                // Apparent solar time reads exactly 12:00 at solar noon
+                let noon = pos.solar_noon(dt);
+                let twelve = noon
+                    .naive_utc()
+                    .date()
+                    .and_hms_opt(12, 0, 0)
+                    .expect("Noon is a valid time");
+                let offset = Utc.from_utc_datetime(&twelve) - noon;
+                FixedOffset::east_opt(offset.num_seconds() as i32)
+                    .expect("Solar noon is within half a day of 12:00 UTC")
+            }
+            Clock::Zone(ZoneArg::Fixed(offset)) => offset,
+            Clock::Zone(ZoneArg::Named(zone)) => {
+                zone.offset_from_utc_datetime(&dt.naive_utc()).fix()
+            }
+        }
+    }
+}
+
+impl SunTimes {
+    fn from_pos(dt: DateTime<Utc>, pos: Pos, clock: Clock) -> Self {
+        let tz = clock.offset(pos, dt);
+        let events = pos.events(dt);
+        SunTimes {
+            sunrise: events.sunrise.with_timezone(&tz),
+            noon: events.solar_noon.with_timezone(&tz),
+            sunset: events.sunset.with_timezone(&tz),
+            twilight: None,
+        }
+    }
+    /// The same times, with twilight added if `twilight` is set
+    fn with_twilight(self, twilight: bool, dt: DateTime<Utc>, pos: Pos, clock: Clock) -> Self {
+        SunTimes {
+            twilight: twilight.then(|| TwilightTimes::new(dt, pos, clock)),
+            ..self
+        }
+    }
+    fn day_length(&self) -> Duration {
+        self.sunset - self.sunrise
+    }
+    /// The same events, shown in `zone` at whatever offset it has on each
+    fn in_zone(&self, zone: &Tz) -> Self {
+        let shift = |dt: DateTime<FixedOffset>| {
+            let local = dt.with_timezone(zone);
+            local.with_timezone(&local.offset().fix())
+        };
+        SunTimes {
+            sunrise: shift(self.sunrise),
+            noon: shift(self.noon),
+            sunset: shift(self.sunset),
+            twilight: self.twilight.map(|twilight| twilight.map(shift)),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct ZonedSunTimes {
+    date: String,
+    zones: BTreeMap<String, SunTimes>,
+}
+
+/// Prints each day's events once per zone in `--timezones`. Events are computed once per day and
+/// only their presentation differs between zones.
+fn zones_output<I: Iterator<Item = DateTime<Utc>>>(
+    range: I,
+    pos: Pos,
+    args: &Args,
+    out: &mut dyn Write,
+) -> Result<(), Box<dyn Error>> {
+    let days: Vec<_> = range
+        .map(|dt| {
+            let times = SunTimes::from_pos(dt, pos, Clock::Local);
+            let zones: Vec<_> = args
+                .timezones
+                .iter()
+                .map(|zone| (zone.name(), times.in_zone(zone)))
+                .collect();
+            (dt.format("%Y-%m-%d").to_string(), zones)
+        })
+        .collect();
+    match args.format {
+        Format::Human => {
+            let name_width = args.timezones.iter().map(|zone| zone.name().len()).max();
+            let name_width = name_width.unwrap_or_default();
+            let markers = args.markers();
+            for (date, zones) in days {
+                for (zone, times) in zones {
+                    writeln!(
+                        out,
+                        "{date} {zone:<name_width$} {} {} {} {} {} {}",
+                        markers.sunrise(),
+                        times.sunrise.format("%H:%M:%S %:z"),
+                        markers.noon(),
+                        times.noon.format("%H:%M:%S %:z"),
+                        markers.sunset(),
+                        times.sunset.format("%H:%M:%S %:z"),
+                    )?;
+                }
+            }
+        }
+        Format::Csv => {
+            writeln!(out, "date,zone,sunrise,noon,sunset")?;
+            for (date, zones) in days {
+                for (zone, times) in zones {
+                    writeln!(
+                        out,
+                        "{date},{zone},{},{},{}",
+                        times.sunrise.to_rfc3339(),
+                        times.noon.to_rfc3339(),
+                        times.sunset.to_rfc3339()
+                    )?;
+                }
+            }
+        }
+        Format::Json => {
+            let output: Vec<_> = days
+                .into_iter()
+                .map(|(date, zones)| ZonedSunTimes {
+                    date,
+                    zones: zones
+                        .into_iter()
+                        .map(|(zone, times)| (zone.to_string(), times))
+                        .collect(),
+                })
+                .collect();
+            writeln!(out, "{}", serde_json::to_string_pretty(&output).unwrap())?;
+        }
+        Format::Plot | Format::Html | Format::Ics | Format::Markdown | Format::Svg => {
+            return Err("--timezones only supports human, csv and json output".into())
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_sun_times_in_zone() {
+    let dt = Utc.with_ymd_and_hms(2023, 7, 1, 12, 0, 0).unwrap();
+    let times = SunTimes::from_pos(dt, Pos::new(51.5, 0.), Clock::TrueSolar);
+    let london = times.in_zone(&chrono_tz::Europe::London);
+    let tokyo = times.in_zone(&chrono_tz::Asia::Tokyo);
+    assert_eq!(london.noon, times.noon);
+    assert_eq!(london.noon.offset().local_minus_utc(), 3600);
+    assert_eq!(tokyo.sunset.offset().local_minus_utc(), 9 * 3600);
+}
+
+/// A day's times in JSON output, along with the moon if asked for
+#[derive(Debug, Serialize)]
+struct WithMoon<T> {
+    #[serde(flatten)]
+    times: T,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    moon: Option<MoonDay>,
+}
+
+/// The moon's phase on a day, and when it rises and sets, if it does
+#[derive(Debug, Serialize)]
+struct MoonDay {
+    #[serde(flatten)]
+    phase: MoonPhase,
+    #[serde(serialize_with = "serialize_opt_dt")]
+    moonrise: Option<DateTime<FixedOffset>>,
+    #[serde(serialize_with = "serialize_opt_dt")]
+    moonset: Option<DateTime<FixedOffset>>,
+}
+
+impl MoonDay {
+    fn new(dt: DateTime<Utc>, pos: Pos, clock: Clock) -> Self {
+        let tz = clock.offset(pos, dt);
+        MoonDay {
+            phase: moon_phase(dt),
+            moonrise: pos.moonrise(dt).map(|time| time.with_timezone(&tz)),
+            moonset: pos.moonset(dt).map(|time| time.with_timezone(&tz)),
+        }
+    }
+}
+
+/// `SunTimes` with each event also given as milliseconds since the Unix epoch, for consumers
+/// (like JavaScript's `Date`) that work in those directly
+#[derive(Debug, Serialize)]
+struct SunTimesEpochMs {
+    #[serde(flatten)]
+    times: SunTimes,
+    sunrise_epoch_ms: i64,
+    noon_epoch_ms: i64,
+    sunset_epoch_ms: i64,
+}
+
+impl SunTimesEpochMs {
+    fn new(times: SunTimes) -> Self {
+        SunTimesEpochMs {
+            sunrise_epoch_ms: times.sunrise.timestamp_millis(),
+            noon_epoch_ms: times.noon.timestamp_millis(),
+            sunset_epoch_ms: times.sunset.timestamp_millis(),
+            times,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct DepressionTimes {
+    date: String,
+    #[serde(serialize_with = "serialize_opt_dt")]
+    dawn: Option<DateTime<FixedOffset>>,
+    #[serde(serialize_with = "serialize_opt_dt")]
+    dusk: Option<DateTime<FixedOffset>>,
+}
+
+/// Prints the morning and evening times the sun is `depression` degrees below the horizon. Days
+/// where it never gets that low (or high) have no time for that event.
+fn depression_output<I: Iterator<Item = DateTime<Utc>>>(
+    range: I,
+    pos: Pos,
+    depression: f64,
+    clock: Clock,
+    args: &Args,
+    out: &mut dyn Write,
+) -> Result<(), Box<dyn Error>> {
+    let rows: Vec<_> = range
+        .map(|dt| {
+            let tz = clock.offset(pos, dt);
+            let noon = pos.solar_noon(dt);
+            DepressionTimes {
+                date: dt.format("%Y-%m-%d").to_string(),
+                dawn: pos
+                    .time_at_elevation(noon, -depression, true)
+                    .map(|dt| dt.with_timezone(&tz)),
+                dusk: pos
+                    .time_at_elevation(noon, -depression, false)
+                    .map(|dt| dt.with_timezone(&tz)),
+            }
+        })
+        .collect();
+    match args.format {
+        Format::Human => {
+            let format = |time: Option<DateTime<FixedOffset>>| {
+                time.map_or_else(
+                    || "--:--:--".to_string(),
+                    |time| time.format("%H:%M:%S").to_string(),
+                )
+            };
+            for row in rows {
+                writeln!(
+                    out,
+                    "{} {depression}° dawn {} dusk {}",
+                    row.date,
+                    format(row.dawn),
+                    format(row.dusk)
+                )?;
+            }
+        }
+        Format::Csv => {
+            writeln!(out, "date,dawn_s,dusk_s")?;
+            let format = |time: Option<DateTime<FixedOffset>>| {
+                time.map_or(String::new(), |time| {
+                    time.num_seconds_from_midnight().to_string()
+                })
+            };
+            for row in rows {
+                writeln!(
+                    out,
+                    "{},{},{}",
+                    row.date,
+                    format(row.dawn),
+                    format(row.dusk)
+                )?;
+            }
+        }
+        Format::Json => writeln!(out, "{}", serde_json::to_string_pretty(&rows).unwrap())?,
+        Format::Plot => {
+            let local_date = |dt: &DateTime<FixedOffset>| dt.naive_local().date();
+            let dusks: Vec<_> = rows.iter().filter_map(|row| row.dusk).collect();
+            let dates: Vec<_> = dusks.iter().map(local_date).collect();
+            plot_times(out, "Dusk", &dates, &dusks, args.plot_style())?;
+            let dawns: Vec<_> = rows.iter().filter_map(|row| row.dawn).collect();
+            let dates: Vec<_> = dawns.iter().map(local_date).collect();
+            plot_times(out, "Dawn", &dates, &dawns, args.plot_style())?;
+        }
+        Format::Svg => return Err("SVG output is only available for sunrise and sunset".into()),
+        Format::Html | Format::Markdown => {
+            let format = |time: Option<DateTime<FixedOffset>>| {
+                time.map_or(String::new(), |time| time.format("%H:%M:%S").to_string())
+            };
+            let rows: Vec<_> = rows
+                .into_iter()
+                .map(|row| vec![row.date, format(row.dawn), format(row.dusk)])
+                .collect();
+            let columns = [("date", "Date"), ("dawn", "Dawn"), ("dusk", "Dusk")];
+            if args.format == Format::Markdown {
+                writeln!(out, "{}", markdown_table(&columns, &rows))?;
+                return Ok(());
+            }
+            let caption = format!(
+                "Sun {depression}° below the horizon at {}",
+                location_label(pos, args)
+            );
+            writeln!(out, "{}", html_table(&caption, &columns, &rows))?;
+        }
+        Format::Ics => {
+            let mut events = vec![];
+            for row in rows {
+                let date = row.date.replace('-', "");
+                for (kind, time) in [("Dawn", row.dawn), ("Dusk", row.dusk)] {
+                    if let Some(time) = time {
+                        events.push(IcsEvent {
+                            uid: format!("{date}-{}-{depression}@suntime", kind.to_lowercase()),
+                            summary: format!("{kind} ({depression}°)"),
+                            start: time.with_timezone(&Utc),
+                        });
+                    }
+                }
+            }
+            write!(out, "{}", ics_calendar(&events, Utc::now(), args.reminder))?;
+        }
+    }
+    Ok(())
+}
+
+/// How human output marks each event
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Markers {
+    Emoji,
+    /// Plain words, for terminals that can't show emoji or don't give them a consistent width
+    Ascii,
+}
+
+impl Markers {
+    fn sunrise(self) -> &'static str {
+        match self {
+            Markers::Emoji => "🌅",
+            Markers::Ascii => "rise",
+        }
+    }
+    fn noon(self) -> &'static str {
+        match self {
+            Markers::Emoji => "🌞",
+            Markers::Ascii => "noon",
+        }
+    }
+    fn sunset(self) -> &'static str {
+        match self {
+            Markers::Emoji => "🌇",
+            Markers::Ascii => "set",
+        }
+    }
+    fn moonrise(self) -> &'static str {
+        match self {
+            Markers::Emoji => "↑",
+            Markers::Ascii => "rise",
+        }
+    }
+    fn moonset(self) -> &'static str {
+        match self {
+            Markers::Emoji => "↓",
+            Markers::Ascii => "set",
+        }
+    }
+    fn moon(self, phase: PhaseName) -> &'static str {
+        match (self, phase) {
+            (Markers::Ascii, _) => "moon",
+            (Markers::Emoji, PhaseName::NewMoon) => "🌑",
+            (Markers::Emoji, PhaseName::WaxingCrescent) => "🌒",
+            (Markers::Emoji, PhaseName::FirstQuarter) => "🌓",
+            (Markers::Emoji, PhaseName::WaxingGibbous) => "🌔",
+            (Markers::Emoji, PhaseName::FullMoon) => "🌕",
+            (Markers::Emoji, PhaseName::WaningGibbous) => "🌖",
+            (Markers::Emoji, PhaseName::LastQuarter) => "🌗",
+            (Markers::Emoji, PhaseName::WaningCrescent) => "🌘",
+        }
+    }
+}
+
+/// Whether emoji are likely to display: the terminal isn't "dumb", and the locale (from the
+/// first of LC_ALL, LC_CTYPE and LANG that's set) is UTF-8
+fn terminal_supports_emoji() -> bool {
+    if std::env::var("TERM").is_ok_and(|term| term == "dumb") {
+        return false;
+    }
+    ["LC_ALL", "LC_CTYPE", "LANG"]
+        .into_iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find(|locale| !locale.is_empty())
+        .is_some_and(|locale| {
+            let locale = locale.to_ascii_lowercase();
+            locale.contains("utf-8") || locale.contains("utf8")
+        })
+}
+
+/// How to lay out a line of human output: how events are marked, whether to end with the day's
+/// length, the moon's phase and the noon shadow ratio, and whether to colour it
+#[derive(Debug, Clone, Copy)]
+struct HumanStyle<'a> {
+    markers: Markers,
+    day_length: bool,
+    color: bool,
+    moon: bool,
+    shadow: bool,
+    /// Today's date, if days next to it should be labelled by name
+    relative_to: Option<NaiveDate>,
+    date_format: &'a str,
+    time_format: &'a str,
+}
+
+impl Default for HumanStyle<'_> {
+    fn default() -> Self {
+        HumanStyle {
+            markers: Markers::Emoji,
+            day_length: false,
+            color: false,
+            moon: false,
+            shadow: false,
+            relative_to: None,
+            date_format: DEFAULT_DATE_FORMAT,
+            time_format: DEFAULT_TIME_FORMAT,
+        }
+    }
+}
+
+const YELLOW: &str = "\x1b[33m";
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const RESET_COLOR: &str = "\x1b[0m";
+
+/// Wraps `text` in `color`'s escape codes if `enabled`
+fn paint(text: String, color: &str, enabled: bool) -> String {
+    if enabled {
+        format!("{color}{text}{RESET_COLOR}")
+    } else {
+        text
+    }
+}
+
+/// Formats `delta` right-aligned in five columns, in green when it's positive and red when it's
+/// negative if `color` is set. Padding comes first so escape codes don't throw the columns off.
+fn paint_delta(delta: Duration, color: bool) -> String {
+    let text = format!("{:>5}", format_duration_ms(delta));
+    match delta.num_seconds() {
+        seconds if seconds > 0 => paint(text, GREEN, color),
+        seconds if seconds < 0 => paint(text, RED, color),
+        _ => text,
+    }
+}
+
+fn human_output(
+    out: &mut dyn Write,
+    dt: DateTime<Utc>,
+    pos: Pos,
+    clock: Clock,
+    style: HumanStyle<'_>,
+) -> io::Result<()> {
+    writeln!(out, "{}", human_row(dt, pos, clock, style))
+}
+
+/// One line of human output. With `style.day_length`, the day's length is repeated at the end,
+/// right-aligned so it lines up whatever its width.
+fn human_row(dt: DateTime<Utc>, pos: Pos, clock: Clock, style: HumanStyle<'_>) -> String {
+    let HumanStyle {
+        markers,
+        day_length: show_day_length,
+        color,
+        moon,
+        shadow,
+        relative_to,
+        date_format,
+        time_format,
+    } = style;
+    let times = SunTimes::from_pos(dt, pos, clock);
+    let tomorrow = SunTimes::from_pos(dt + Duration::days(1), pos, clock);
+
+    // Compare times of day rather than instants, as the clock's offset may differ between days
+    let sunset_delta =
+        (tomorrow.sunset.naive_local() - times.sunset.naive_local()) - Duration::days(1);
+    let sunrise_delta =
+        (tomorrow.sunrise.naive_local() - times.sunrise.naive_local()) - Duration::days(1);
+    let day_length = times.day_length();
+    let tomorrow_day_length = tomorrow.day_length();
+    let day_length_delta = tomorrow_day_length - day_length;
+
+    let row = format!("{date} {rise} {sunrise} (Δ{sunrise_delta}) {noon_marker} {noon} ({day_length} Δ{day_length_delta}) {set} {sunset} (Δ{sunset_delta})",
+        date=day_label(dt.naive_utc().date(), relative_to, date_format),
+        rise=markers.sunrise(),
+        noon_marker=markers.noon(),
+        set=markers.sunset(),
+        sunrise=paint(times.sunrise.format(time_format).to_string(), YELLOW, color),
+        sunrise_delta=paint_delta(sunrise_delta, color),
+        noon=times.noon.format(time_format),
+        day_length=format_duration_hms(day_length),
+        day_length_delta=paint_delta(day_length_delta, color),
+        sunset=paint(times.sunset.format(time_format).to_string(), RED, color),
+        sunset_delta=paint_delta(sunset_delta, color)
+    );
+    let mut row = row;
+    if show_day_length {
+        row = format!("{row} Day: {:>8}", format_duration_hms(day_length));
+    }
+    if moon {
+        let MoonDay {
+            phase,
+            moonrise,
+            moonset,
+        } = MoonDay::new(dt, pos, clock);
+        let format = |time: Option<DateTime<FixedOffset>>| {
+            time.map_or_else(
+                || "--:--".to_string(),
+                |time| time.format("%H:%M").to_string(),
+            )
+        };
+        row = format!(
+            "{row} {} {} {:.0}% {} {} {} {}",
+            markers.moon(phase.name),
+            phase.name,
+            phase.illumination * 100.,
+            markers.moonrise(),
+            format(moonrise),
+            markers.moonset(),
+            format(moonset)
+        );
+    }
+    if shadow {
+        // Dashes during polar night, when there's no noon shadow
+        let ratio = pos
+            .shadow_ratio(pos.solar_noon(dt))
+            .map_or_else(|| "--".to_string(), |ratio| format!("{ratio:.2}"));
+        row = format!("{row} Shadow: {ratio:>6}");
+    }
+    row
+}
+
+/// The date in `date_format`, or "Yesterday", "Today" or "Tomorrow" if it's next to `today`,
+/// padded to the date's width so the rest of the row still lines up
+fn day_label(date: NaiveDate, today: Option<NaiveDate>, date_format: &str) -> String {
+    let label = today.and_then(|today| match (date - today).num_days() {
+        -1 => Some("Yesterday"),
+        0 => Some("Today"),
+        1 => Some("Tomorrow"),
+        _ => None,
+    });
+    let formatted = date.format(date_format).to_string();
+    match label {
+        Some(label) => format!("{label:<width$}", width = formatted.chars().count()),
+        None => formatted,
+    }
+}
+
+/// Prints a day as a card of aligned label/value lines. Events that don't happen on the day
+/// (such as sunrise in polar night, or civil twilight near midsummer at high latitudes) are
+/// shown as dashes. The moon's phase is always shown, and with `style.moon` its rise and set.
+fn almanac_output(
+    out: &mut dyn Write,
+    dt: DateTime<Utc>,
+    pos: Pos,
+    clock: Clock,
+    style: HumanStyle,
+) -> io::Result<()> {
+    let markers = style.markers;
+    let times = SunTimes::from_pos(dt, pos, clock);
+    let tomorrow = SunTimes::from_pos(dt + Duration::days(1), pos, clock);
+    let tz = clock.offset(pos, dt);
+    let (max_altitude, _) = pos.solar_position(times.noon.with_timezone(&Utc));
+    let civil = pos.all_twilights(times.noon.with_timezone(&Utc)).civil;
+    let format = |time: Option<DateTime<Utc>>| {
+        time.map_or_else(
+            || "--:--:--".to_string(),
+            |time| time.with_timezone(&tz).format("%H:%M:%S").to_string(),
+        )
+    };
+
+    let day_length = times.day_length();
+    let day_length_delta = tomorrow.day_length() - day_length;
+    let trend = match day_length_delta.num_seconds() {
+        delta if delta > 0 => '↑',
+        delta if delta < 0 => '↓',
+        _ => '→',
+    };
+
+    // Without emoji, the labels alone line up
+    writeln!(out, "{}", dt.format("%Y-%m-%d %A"))?;
+    let mut line = |emoji: &str, text: String| match markers {
+        Markers::Emoji => writeln!(out, "  {emoji} {text}"),
+        Markers::Ascii => writeln!(out, "  {text}"),
+    };
+    line(
+        "🌅",
+        format!("Sunrise     {}", format(pos.try_sunrise(dt).ok())),
+    )?;
+    line(
+        "🌞",
+        format!(
+            "Solar noon  {} (altitude {max_altitude:.1}°)",
+            times.noon.format("%H:%M:%S")
+        ),
+    )?;
+    line(
+        "🌇",
+        format!("Sunset      {}", format(pos.try_sunset(dt).ok())),
+    )?;
+    line(
+        "⏳",
+        format!(
+            "Day length  {} {trend} {}",
+            format_duration_hms(day_length),
+            format_duration_ms(day_length_delta)
+        ),
+    )?;
+    line(
+        "🌆",
+        format!(
+            "Civil       {} – {}",
+            format(civil.dawn),
+            format(civil.dusk)
+        ),
+    )?;
+    let MoonDay {
+        phase,
+        moonrise,
+        moonset,
+    } = MoonDay::new(dt, pos, clock);
+    let mut moon = format!(
+        "Moon        {} {:.0}%",
+        phase.name,
+        phase.illumination * 100.
+    );
+    if style.moon {
+        let utc = |time: Option<DateTime<FixedOffset>>| time.map(|time| time.with_timezone(&Utc));
+        moon = format!(
+            "{moon} {} {} {} {}",
+            markers.moonrise(),
+            format(utc(moonrise)),
+            markers.moonset(),
+            format(utc(moonset))
+        );
+    }
+    line(markers.moon(phase.name), moon)
+}
+
+#[test]
+fn test_almanac_output() {
+    let clock = Clock::Zone(ZoneArg::Fixed(FixedOffset::east_opt(0).unwrap()));
+    let style = HumanStyle {
+        markers: Markers::Ascii,
+        ..HumanStyle::default()
+    };
+    let card = |dt, pos, style| {
+        let mut out = vec![];
+        almanac_output(&mut out, dt, pos, clock, style).unwrap();
+        String::from_utf8(out).unwrap()
+    };
+    let dt = Utc.with_ymd_and_hms(2023, 3, 20, 12, 0, 0).unwrap();
+    let london = Pos::new(51.5, 0.);
+    let text = card(dt, london, style);
+    let sunrise = london.sunrise(dt).format("%H:%M:%S");
+    assert!(
+        text.contains(&format!("  Sunrise     {sunrise}\n")),
+        "{text}"
+    );
+    let phase = moon_phase(dt);
+    assert!(
+        text.contains(&format!("  Moon        {} ", phase.name)),
+        "{text}"
+    );
+    assert!(!text.contains(" rise "), "{text}");
+    let text = card(
+        dt,
+        london,
+        HumanStyle {
+            moon: true,
+            ..style
+        },
+    );
+    assert!(text.contains(" rise ") && text.contains(" set "), "{text}");
+
+    // No sunrise or sunset in polar night, though noon still comes
+    let dt = Utc.with_ymd_and_hms(2023, 12, 21, 12, 0, 0).unwrap();
+    let text = card(dt, Pos::new(78.2, 15.6), style);
+    assert!(text.contains("  Sunrise     --:--:--\n"), "{text}");
+    assert!(text.contains("  Sunset      --:--:--\n"), "{text}");
+    assert!(text.contains("  Solar noon  "), "{text}");
+}
+
+/// Column names for `csv_row`. Times are seconds since local midnight. On days the sun doesn't
+/// rise or set, the sunrise and sunset fields are left empty and the day length is 86400 during
+/// polar day or 0 during polar night.
+const CSV_HEADER: &str = "date,sunrise_s,noon_s,sunset_s,day_length_s";
+
+/// Twilight columns added by --twilight. Like sunrise and sunset, they're seconds since local
+/// midnight, and empty on days the sun doesn't reach that depression.
+const CSV_TWILIGHT_HEADER: &str = "civil_dawn_s,civil_dusk_s,nautical_dawn_s,nautical_dusk_s,\
+                                   astronomical_dawn_s,astronomical_dusk_s";
+
+/// Column names for comparing two locations in CSV: the date, then `CSV_HEADER`'s columns for
+/// each location with `_1` or `_2` added, then how much later the second location's sunrise is
+fn compare_csv_header() -> String {
+    let columns = |n| {
+        CSV_HEADER
+            .split(',')
+            .skip(1)
+            .map(move |column| format!("{column}_{n}"))
+    };
+    let columns: Vec<_> = columns(1).chain(columns(2)).collect();
+    format!("date,{},sunrise_delta_s", columns.join(","))
+}
+
+/// `CSV_HEADER`, plus the twilight columns with --twilight and the moon's with --moon. Like the
+/// sun's, moonrise and moonset are seconds since local midnight, and empty on days there isn't one
+fn csv_header(args: &Args) -> String {
+    let mut header = CSV_HEADER.to_string();
+    if args.twilight {
+        header = format!("{header},{CSV_TWILIGHT_HEADER}");
+    }
+    if args.moon {
+        header = format!("{header},moon_illumination,moon_phase,moonrise_s,moonset_s");
+    }
+    header
+}
+
+fn csv_output(
+    out: &mut dyn Write,
+    dt: DateTime<Utc>,
+    pos: Pos,
+    clock: Clock,
+    twilight: bool,
+    moon: bool,
+    date_format: &str,
+) -> io::Result<()> {
+    let mut row = csv_row(dt, pos, clock, date_format);
+    let day_start = day_start(&SunTimes::from_pos(dt, pos, clock));
+    let seconds = |time: Option<DateTime<FixedOffset>>| {
+        time.map_or(String::new(), |time| {
+            (time - day_start).num_seconds().to_string()
+        })
+    };
+    if twilight {
+        for time in TwilightTimes::new(dt, pos, clock).times() {
+            row = format!("{row},{}", seconds(time));
+        }
+    }
+    if moon {
+        let MoonDay {
+            phase,
+            moonrise,
+            moonset,
+        } = MoonDay::new(dt, pos, clock);
+        row = format!(
+            "{row},{:.3},{},{},{}",
+            phase.illumination,
+            phase.name,
+            seconds(moonrise),
+            seconds(moonset)
+        );
+    }
+    writeln!(out, "{row}")
+}
+
+/// Local midnight at the start of the day `times` are for
+fn day_start(times: &SunTimes) -> DateTime<FixedOffset> {
+    times
+        .noon
+        .with_hour(0)
+        .unwrap()
+        .with_minute(0)
+        .unwrap()
+        .with_second(0)
+        .unwrap()
+        .with_nanosecond(0)
+        .unwrap()
+}
+
+fn csv_row(dt: DateTime<Utc>, pos: Pos, clock: Clock, date_format: &str) -> String {
+    format!("{},{}", dt.format(date_format), csv_times(dt, pos, clock))
+}
+
+/// The fields of `csv_row` after the date
+fn csv_times(dt: DateTime<Utc>, pos: Pos, clock: Clock) -> String {
+    let times = SunTimes::from_pos(dt, pos, clock);
+
+    let day_start = day_start(&times);
+    let noon = (times.noon - day_start).num_seconds();
+
+    match pos.try_sunrise(pos.solar_noon(dt)) {
+        // Day length is zero or a full day when the sun doesn't cross the horizon
+        Err(_) => format!(",{noon},,{}", times.day_length().num_seconds()),
+        Ok(_) => format!(
+            "{sunrise},{noon},{sunset},{day_length}",
+            sunrise = (times.sunrise - day_start).num_seconds(),
+            sunset = (times.sunset - day_start).num_seconds(),
+            day_length = times.day_length().num_seconds()
+        ),
+    }
+}
+
+#[test]
+fn test_range_accuracy_warnings() {
+    let dt = |year| Utc.with_ymd_and_hms(year, 6, 21, 12, 0, 0).unwrap();
+    let oslo = Pos::new(59.9, 10.75);
+    assert!(range_accuracy_warnings(oslo, &[dt(2099), dt(2100)]).is_empty());
+    // A range that only reaches a distant year partway through still warns about it
+    let warnings = range_accuracy_warnings(oslo, &[dt(2100), dt(2101), dt(2102)]);
+    assert_eq!(
+        warnings,
+        [
+            AccuracyWarning::DistantYear(2101),
+            AccuracyWarning::DistantYear(2102)
+        ]
+    );
+    // The latitude is the same every year, so it's only mentioned once
+    let tromso = Pos::new(69.65, 18.96);
+    let warnings = range_accuracy_warnings(tromso, &[dt(2023), dt(2024)]);
+    assert_eq!(warnings, [AccuracyWarning::HighLatitude(69.65)]);
+}
+
+#[test]
+fn test_range_summary() {
+    let pos = Pos::new(51.5, 0.);
+    assert!(RangeSummary::new(std::iter::empty(), pos, Clock::TrueSolar).is_none());
+    let days = |dates: &[(u32, u32)]| {
+        let dates: Vec<_> = dates
+            .iter()
+            .map(|&(month, day)| Utc.with_ymd_and_hms(2023, month, day, 12, 0, 0).unwrap())
+            .collect();
+        dates.into_iter()
+    };
+    let seasons = [(3, 20), (6, 21), (9, 22), (12, 21)];
+    let summary = RangeSummary::new(days(&seasons), pos, Clock::TrueSolar).unwrap();
+    assert_eq!(summary.longest_day.date, "2023-06-21");
+    assert_eq!(summary.shortest_day.date, "2023-12-21");
+    assert_eq!(summary.earliest_sunrise.unwrap().date, "2023-06-21");
+    assert_eq!(summary.latest_sunset.unwrap().date, "2023-06-21");
+    assert!(summary.longest_day.day_length_s > 16 * 3600);
+    assert!(summary.shortest_day.day_length_s < 8 * 3600);
+
+    // The midnight sun has no real sunrise or sunset to count
+    let tromso = Pos::new(69.65, 18.96);
+    let dates = [(3, 20), (6, 21), (12, 21)];
+    let summary = RangeSummary::new(days(&dates), tromso, Clock::TrueSolar).unwrap();
+    assert_eq!(summary.longest_day.day_length_s, 86400);
+    assert_eq!(summary.earliest_sunrise.unwrap().date, "2023-03-20");
+    assert_eq!(summary.latest_sunset.unwrap().date, "2023-03-20");
+    let summary = RangeSummary::new(days(&[(6, 21)]), tromso, Clock::TrueSolar).unwrap();
+    assert!(summary.earliest_sunrise.is_none() && summary.latest_sunset.is_none());
+    assert_eq!(time_extreme(&summary.earliest_sunrise), "none");
+}
+
+#[test]
+fn test_csv_row_polar() {
+    let pos = Pos::new(80., 0.);
+    let row = |month| {
+        let dt = Utc.with_ymd_and_hms(2022, month, 21, 12, 0, 0).unwrap();
+        let row = csv_row(dt, pos, Clock::TrueSolar, DEFAULT_DATE_FORMAT);
+        row.split(',').map(str::to_string).collect::<Vec<_>>()
+    };
+
+    let night = row(12);
+    assert_eq!(night[0], "2022-12-21");
+    assert_eq!((night[1].as_str(), night[3].as_str()), ("", ""));
+    assert!((night[2].parse::<i64>().unwrap() - 43200).abs() <= 1);
+    assert_eq!(night[4], "0");
+
+    let day = row(6);
+    assert_eq!((day[1].as_str(), day[3].as_str()), ("", ""));
+    assert_eq!(day[4], "86400");
 }
 
 // fn info_for_day(dt: DateTime<Utc>, pos: Pos, format: Format) {
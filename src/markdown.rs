@@ -0,0 +1,70 @@
+fn escape(text: &str) -> String {
+    text.replace('|', "\\|")
+}
+
+/// Renders a GitHub-flavoured Markdown table, padding each column to its widest cell so the
+/// source lines up too. `columns` takes the same `(class, heading)` pairs as `html_table`; only
+/// the headings are used.
+pub fn markdown_table(columns: &[(&str, &str)], rows: &[Vec<String>]) -> String {
+    let headings: Vec<_> = columns.iter().map(|(_, heading)| escape(heading)).collect();
+    let rows: Vec<Vec<_>> = rows
+        .iter()
+        .map(|row| row.iter().map(|cell| escape(cell)).collect())
+        .collect();
+    let widths: Vec<_> = headings
+        .iter()
+        .enumerate()
+        .map(|(i, heading)| {
+            rows.iter()
+                .filter_map(|row| row.get(i))
+                .chain([heading])
+                .map(|cell| cell.chars().count())
+                .max()
+                .unwrap_or_default()
+                // A separator needs at least three dashes
+                .max(3)
+        })
+        .collect();
+    let line = |cells: &[String]| {
+        let cells: Vec<_> = widths
+            .iter()
+            .enumerate()
+            .map(|(i, width)| {
+                let cell = cells.get(i).map_or("", String::as_str);
+                format!("{cell:<width$}")
+            })
+            .collect();
+        format!("| {} |", cells.join(" | "))
+    };
+    let separator: Vec<_> = widths.iter().map(|width| "-".repeat(*width)).collect();
+
+    let mut lines = vec![line(&headings), line(&separator)];
+    lines.extend(rows.iter().map(|row| line(row)));
+    lines.join("\n")
+}
+
+#[test]
+fn test_markdown_table() {
+    let table = markdown_table(
+        &[("date", "Date"), ("sunrise", "Sunrise"), ("note", "A|B")],
+        &[
+            vec![
+                "2023-01-01".to_string(),
+                "07:45:00".to_string(),
+                "x".to_string(),
+            ],
+            vec![
+                "2023-01-02".to_string(),
+                "07:44:59".to_string(),
+                "".to_string(),
+            ],
+        ],
+    );
+    assert_eq!(
+        table,
+        "| Date       | Sunrise  | A\\|B |\n\
+         | ---------- | -------- | ---- |\n\
+         | 2023-01-01 | 07:45:00 | x    |\n\
+         | 2023-01-02 | 07:44:59 |      |"
+    );
+}
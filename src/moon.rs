@@ -0,0 +1,258 @@
+//! The moon's phase, counted in mean synodic months from a known new moon, and when it rises
+//! and sets. The real moon runs up to a day or so ahead of or behind the mean phase, which is
+//! plenty for naming it; rise and set use a low-precision lunar position good to a few minutes.
+
+use std::f64::consts::TAU;
+use std::fmt;
+
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use serde::Serialize;
+
+use crate::Pos;
+
+/// Mean time from one new moon to the next, in days
+const SYNODIC_MONTH: f64 = 29.530_588_853;
+
+/// The new moon of 2000-01-06, 18:14 UTC
+fn new_moon_epoch() -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(2000, 1, 6, 18, 14, 0).unwrap()
+}
+
+/// The eight traditional phases, each covering an eighth of the month centred on its namesake
+/// moment (so "Full Moon" spans the day or so either side of full)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PhaseName {
+    NewMoon,
+    WaxingCrescent,
+    FirstQuarter,
+    WaxingGibbous,
+    FullMoon,
+    WaningGibbous,
+    LastQuarter,
+    WaningCrescent,
+}
+
+impl fmt::Display for PhaseName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            PhaseName::NewMoon => "New Moon",
+            PhaseName::WaxingCrescent => "Waxing Crescent",
+            PhaseName::FirstQuarter => "First Quarter",
+            PhaseName::WaxingGibbous => "Waxing Gibbous",
+            PhaseName::FullMoon => "Full Moon",
+            PhaseName::WaningGibbous => "Waning Gibbous",
+            PhaseName::LastQuarter => "Last Quarter",
+            PhaseName::WaningCrescent => "Waning Crescent",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct MoonPhase {
+    /// Fraction of the disc that's lit, from 0 at new moon to 1 at full
+    pub illumination: f64,
+    /// Days since the last new moon
+    pub age_days: f64,
+    pub name: PhaseName,
+}
+
+/// The moon's phase at `dt`. It's the same the world over, so there's no position.
+pub fn moon_phase(dt: DateTime<Utc>) -> MoonPhase {
+    let days = (dt - new_moon_epoch()).num_seconds() as f64 / 86_400.;
+    let age_days = days.rem_euclid(SYNODIC_MONTH);
+    let fraction = age_days / SYNODIC_MONTH;
+    let name = match (fraction * 8. + 0.5) as usize % 8 {
+        0 => PhaseName::NewMoon,
+        1 => PhaseName::WaxingCrescent,
+        2 => PhaseName::FirstQuarter,
+        3 => PhaseName::WaxingGibbous,
+        4 => PhaseName::FullMoon,
+        5 => PhaseName::WaningGibbous,
+        6 => PhaseName::LastQuarter,
+        _ => PhaseName::WaningCrescent,
+    };
+    MoonPhase {
+        illumination: (1. - (fraction * TAU).cos()) / 2.,
+        age_days,
+        name,
+    }
+}
+
+/// Degrees to radians, for the position series below, which are all given in degrees
+fn sin_deg(degrees: f64) -> f64 {
+    degrees.to_radians().sin()
+}
+
+fn cos_deg(degrees: f64) -> f64 {
+    degrees.to_radians().cos()
+}
+
+/// Where the moon is, as seen from the centre of the Earth
+struct MoonPosition {
+    /// Right ascension, in radians
+    right_ascension: f64,
+    /// Declination, in radians
+    declination: f64,
+    /// Horizontal parallax, in degrees: how far the moon is shifted down from its geocentric
+    /// position when seen on the horizon
+    parallax: f64,
+    /// Days since J2000.0
+    days: f64,
+}
+
+/// The main terms of the moon's ecliptic longitude, latitude and parallax, from the Astronomical
+/// Almanac's low-precision formulae (good to about 0.3° in position)
+fn moon_position(dt: DateTime<Utc>) -> MoonPosition {
+    let j2000 = Utc.with_ymd_and_hms(2000, 1, 1, 12, 0, 0).unwrap();
+    let days = (dt - j2000).num_seconds() as f64 / 86_400.;
+    let t = days / 36_525.;
+    let longitude = 218.32 + 481_267.881 * t
+        + 6.29 * sin_deg(135.0 + 477_198.87 * t)
+        - 1.27 * sin_deg(259.3 - 413_335.36 * t)
+        + 0.66 * sin_deg(235.7 + 890_534.22 * t)
+        + 0.21 * sin_deg(269.9 + 954_397.74 * t)
+        - 0.19 * sin_deg(357.5 + 35_999.05 * t)
+        - 0.11 * sin_deg(186.5 + 966_404.03 * t);
+    let latitude = 5.13 * sin_deg(93.3 + 483_202.02 * t)
+        + 0.28 * sin_deg(228.2 + 960_400.89 * t)
+        - 0.28 * sin_deg(318.3 + 6_003.15 * t)
+        - 0.17 * sin_deg(217.6 - 407_332.21 * t);
+    let parallax = 0.9508
+        + 0.0518 * cos_deg(135.0 + 477_198.87 * t)
+        + 0.0095 * cos_deg(259.3 - 413_335.36 * t)
+        + 0.0078 * cos_deg(235.7 + 890_534.22 * t)
+        + 0.0028 * cos_deg(269.9 + 954_397.74 * t);
+    // Obliquity of the ecliptic
+    let obliquity = 23.439 - 0.000_000_4 * days;
+    let right_ascension = f64::atan2(
+        sin_deg(longitude) * cos_deg(obliquity) - latitude.to_radians().tan() * sin_deg(obliquity),
+        cos_deg(longitude),
+    );
+    let declination = (sin_deg(latitude) * cos_deg(obliquity)
+        + cos_deg(latitude) * sin_deg(obliquity) * sin_deg(longitude))
+    .asin();
+    MoonPosition { right_ascension, declination, parallax, days }
+}
+
+impl Pos {
+    /// How far the moon's centre is above the altitude at which its upper limb touches the
+    /// horizon, in degrees, allowing for parallax, refraction and the moon's radius
+    fn moon_height(self, dt: DateTime<Utc>) -> f64 {
+        let moon = moon_position(dt);
+        let sidereal_time = 280.460_618_37 + 360.985_647_366_29 * moon.days;
+        let hour_angle = (sidereal_time + self.long()).to_radians() - moon.right_ascension;
+        let lat = self.lat().to_radians();
+        let altitude = (lat.sin() * moon.declination.sin()
+            + lat.cos() * moon.declination.cos() * hour_angle.cos())
+        .asin()
+        .to_degrees();
+        altitude - (0.7275 * moon.parallax - 0.5667)
+    }
+
+    /// Finds when the moon rises (if `rising`) or sets in the 24 hours centred on solar noon,
+    /// by checking its height every hour and then interpolating within the hour it crosses
+    fn moon_crossing(self, dt: DateTime<Utc>, rising: bool) -> Option<DateTime<Utc>> {
+        let start = self.solar_noon(dt) - Duration::hours(12);
+        let crosses = |before: f64, after: f64| {
+            if rising {
+                before < 0. && after >= 0.
+            } else {
+                before >= 0. && after < 0.
+            }
+        };
+        let mut before = (start, self.moon_height(start));
+        for hour in 1..=24 {
+            let time = start + Duration::hours(hour);
+            let after = (time, self.moon_height(time));
+            if crosses(before.1, after.1) {
+                return Some(self.interpolate_crossing(before, after));
+            }
+            before = after;
+        }
+        None
+    }
+
+    /// Narrows in on where the moon's height crosses zero between two samples either side of it,
+    /// by repeatedly interpolating linearly and keeping the half that still straddles zero
+    fn interpolate_crossing(
+        self,
+        mut before: (DateTime<Utc>, f64),
+        mut after: (DateTime<Utc>, f64),
+    ) -> DateTime<Utc> {
+        let mut estimate = before.0;
+        for _ in 0..3 {
+            let fraction = before.1 / (before.1 - after.1);
+            let span = (after.0 - before.0).num_milliseconds() as f64;
+            estimate = before.0 + Duration::milliseconds((fraction * span) as i64);
+            let height = self.moon_height(estimate);
+            if (height < 0.) == (before.1 < 0.) {
+                before = (estimate, height);
+            } else {
+                after = (estimate, height);
+            }
+        }
+        estimate
+    }
+
+    /// When the moon's upper limb comes over the horizon in the 24 hours centred on solar noon on
+    /// the day of `dt`. As the moon rises about 50 minutes later each day, roughly one day a month
+    /// has no moonrise; at high latitudes it can also stay up or down for days.
+    pub fn moonrise(self, dt: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.moon_crossing(dt, true)
+    }
+
+    /// When the moon sets in the 24 hours centred on solar noon on the day of `dt`. Like
+    /// `moonrise`, there isn't always one.
+    pub fn moonset(self, dt: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.moon_crossing(dt, false)
+    }
+}
+
+#[test]
+fn test_moon_phase() {
+    let at = |y, m, d, h, min| moon_phase(Utc.with_ymd_and_hms(y, m, d, h, min, 0).unwrap());
+    // The new moon of the 2024 North American total eclipse
+    let new = at(2024, 4, 8, 18, 21);
+    assert_eq!(new.name, PhaseName::NewMoon);
+    assert!(new.illumination < 0.01, "{new:?}");
+    assert!(new.age_days < 1. || new.age_days > SYNODIC_MONTH - 1., "{new:?}");
+    // The "super blue moon" of August 2023
+    let full = at(2023, 8, 31, 1, 36);
+    assert_eq!(full.name, PhaseName::FullMoon);
+    assert!(full.illumination > 0.99, "{full:?}");
+    let first_quarter = at(2023, 3, 28, 2, 32);
+    assert_eq!(first_quarter.name, PhaseName::FirstQuarter);
+    assert!((0.3..0.7).contains(&first_quarter.illumination), "{first_quarter:?}");
+    // Before the epoch too: the new moon a week before Apollo 11 landed
+    assert_eq!(at(1969, 7, 14, 2, 11).name, PhaseName::NewMoon);
+    assert_eq!(PhaseName::WaxingGibbous.to_string(), "Waxing Gibbous");
+}
+
+#[test]
+fn test_moonrise_moonset() {
+    // A full moon rises around sunset and sets around sunrise
+    let london = Pos::new(51.5, 0.);
+    let dt = Utc.with_ymd_and_hms(2023, 8, 31, 12, 0, 0).unwrap();
+    let moonrise = london.moonrise(dt).unwrap();
+    assert!((moonrise - london.sunset(dt)).num_minutes().abs() < 45, "{moonrise}");
+    let moonset = london.moonset(dt).unwrap();
+    assert!((moonset - london.sunrise(dt)).num_minutes().abs() < 45, "{moonset}");
+    // The moon's upper limb is on the horizon at the times found
+    for time in [moonrise, moonset] {
+        assert!(london.moon_height(time).abs() < 0.01, "{time}");
+    }
+    // A new moon rises and sets with the sun
+    let new_york = Pos::new(40.7, -74.);
+    let dt = Utc.with_ymd_and_hms(2024, 4, 8, 12, 0, 0).unwrap();
+    let moonrise = new_york.moonrise(dt).unwrap();
+    assert!((moonrise - new_york.sunrise(dt)).num_minutes().abs() < 30, "{moonrise}");
+    let moonset = new_york.moonset(dt).unwrap();
+    assert!((moonset - new_york.sunset(dt)).num_minutes().abs() < 30, "{moonset}");
+
+    // Rising 50 minutes later each day, the moon skips a day's moonrise about once a month
+    let start = Utc.with_ymd_and_hms(2023, 9, 1, 12, 0, 0).unwrap();
+    let days = (0..30).map(|day| start + Duration::days(day));
+    let missing = days.filter(|dt| london.moonrise(*dt).is_none()).count();
+    assert_eq!(missing, 1);
+}
@@ -1,4 +1,6 @@
-use chrono::{DateTime, FixedOffset};
+use std::io::{self, Write};
+
+use chrono::{DateTime, Datelike, Duration, FixedOffset, NaiveDate, NaiveTime};
 
 
 fn pattern_from_char(ch: char) -> u8 {
@@ -34,6 +36,66 @@ fn char_for_pattern(pattern: u8) -> char {
     }
 }
 
+/// How a cell's braille dot pattern is drawn on the terminal
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Glyphs {
+    Braille,
+    /// For terminals and fonts without braille, or output headed somewhere that mangles it.
+    /// Trades vertical resolution for a rough shape: `.`, `-` and `'` for dots low, in the middle
+    /// and high in the cell, `|` for a steep run through most of it.
+    Ascii,
+}
+
+impl Glyphs {
+    /// Fills empty cells along a gridline
+    fn grid(self) -> char {
+        match self {
+            Glyphs::Braille => '·',
+            Glyphs::Ascii => '_',
+        }
+    }
+
+    fn glyph(self, pattern: u8) -> char {
+        match self {
+            Glyphs::Braille => char_for_pattern(pattern),
+            Glyphs::Ascii => {
+                // Fold both columns together; bit 3 is the bottom dot and bit 0 the top
+                let rows = (pattern | pattern >> 4) & 0b1111;
+                match rows {
+                    0 => ' ',
+                    _ if rows.count_ones() >= 3 => '|',
+                    _ if rows & 0b0011 == 0 => '.',
+                    _ if rows & 0b1100 == 0 => '\'',
+                    _ => '-',
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_ascii_glyphs() {
+    assert_eq!(Glyphs::Ascii.glyph(0), ' ');
+    assert_eq!(Glyphs::Ascii.glyph(0b1000_1000), '.');
+    assert_eq!(Glyphs::Ascii.glyph(0b0100), '.');
+    assert_eq!(Glyphs::Ascii.glyph(0b0110), '-');
+    assert_eq!(Glyphs::Ascii.glyph(0b0001_0010), '\'');
+    assert_eq!(Glyphs::Ascii.glyph(0b1111), '|');
+    assert_eq!(Glyphs::Ascii.glyph(0b0111_1000), '|');
+    assert_eq!(Glyphs::Braille.glyph(0b1111_1111), '⣿');
+
+    let start = DateTime::parse_from_rfc3339("2022-01-01T06:00:00+00:00").unwrap();
+    let times: Vec<_> = (0..10).map(|day| start + Duration::days(day) + Duration::hours(day)).collect();
+    let braille = render_times("Test", &[], &times, test_style(10, 4));
+    let ascii = render_times("Test", &[], &times, PlotStyle { glyphs: Glyphs::Ascii, ..test_style(10, 4) });
+    assert!(ascii.iter().all(|row| row.is_ascii()));
+    for (braille, ascii) in braille.iter().zip(&ascii) {
+        let braille: Vec<_> = braille.chars().map(|ch| ch == ' ').collect();
+        let ascii: Vec<_> = ascii.chars().map(|ch| ch == ' ').collect();
+        assert_eq!(braille, ascii);
+    }
+}
+
 fn plot_at(pattern: u8, buf: &mut char) {
     let pattern = pattern | pattern_from_char(*buf);
     let ch = char_for_pattern(pattern);
@@ -130,32 +192,508 @@ fn plot_line(from: (i64, i64), to: (i64, i64), buf: &mut [Vec<char>]) {
     }
 }
 
-pub fn plot_times(label: &str, width: usize, height: usize, times: &[DateTime<FixedOffset>]) {
-    let times: Vec<_> = times.iter().map(|dt| dt.time()).collect();
-    let min = *times.iter().min().unwrap();
-    let max = *times.iter().max().unwrap();
-    let duration = max - min;
-    let row_height = duration / height as i32;
-    let pt_height = row_height / 4;
-    let mut buf = vec![vec![' '; width]; height + 1];
+/// How to draw a plot: its size in characters (not counting the gutter, axis and legend), the
+/// glyphs for the data, whether to add horizontal gridlines, and whether charts of several
+/// series may use colour
+#[derive(Clone, Copy, Debug)]
+pub struct PlotStyle {
+    pub width: usize,
+    pub height: usize,
+    pub glyphs: Glyphs,
+    pub grid: bool,
+    pub color: bool,
+}
+
+/// Rows between gridlines, counting up from the bottom of the plot
+const GRID_SPACING: usize = 5;
+
+/// Plots `times` by time of day. `dates` gives the day of each point for the x-axis labels, and
+/// may be empty if the x-axis isn't days.
+pub fn plot_times(out: &mut dyn Write, label: &str, dates: &[NaiveDate], times: &[DateTime<FixedOffset>], style: PlotStyle) -> io::Result<()> {
+    write_rows(out, render_times(label, dates, times, style))
+}
+
+fn write_rows(out: &mut dyn Write, rows: Vec<String>) -> io::Result<()> {
+    rows.iter().try_for_each(|row| writeln!(out, "{row}"))
+}
+
+/// Picks one representative (the middle) point for each column when there are more points than
+/// columns, so each column gets a single coherent segment rather than many overlapping ones.
+fn downsample(times: Vec<Duration>, width: usize) -> Vec<Duration> {
+    if times.len() <= width {
+        return times;
+    }
+    (0..width)
+        .map(|col| {
+            let start = col * times.len() / width;
+            let end = (col + 1) * times.len() / width;
+            times[(start + end) / 2]
+        })
+        .collect()
+}
+
+fn render_times(label: &str, dates: &[NaiveDate], times: &[DateTime<FixedOffset>], style: PlotStyle) -> Vec<String> {
+    render_series(label, dates, &[(label, times)], style, false)
+}
+
+/// Plots a quantity, such as the length of each day, with the axis labelled in hours and minutes
+pub fn plot_durations(out: &mut dyn Write, label: &str, dates: &[NaiveDate], values: &[Duration], style: PlotStyle) -> io::Result<()> {
+    write_rows(out, render_durations(label, dates, values, style))
+}
+
+fn render_durations(label: &str, dates: &[NaiveDate], values: &[Duration], style: PlotStyle) -> Vec<String> {
+    let format = |value: Duration| format!("{}:{:02}", value.num_hours(), value.num_minutes() % 60);
+    render_values(label, dates, &[(label, values.to_vec())], format, style, false)
+}
+
+/// Plots several series on one chart with a shared time axis, followed by a legend. When
+/// `style.color` is set, each cell only one series passes through is drawn in that series' colour.
+pub fn plot_times_multi(out: &mut dyn Write, label: &str, dates: &[NaiveDate], series: &[(&str, &[DateTime<FixedOffset>])], style: PlotStyle) -> io::Result<()> {
+    write_rows(out, render_series(label, dates, series, style, style.color))
+}
+
+const SERIES_COLORS: [&str; 4] = ["\x1b[33m", "\x1b[31m", "\x1b[36m", "\x1b[35m"];
+const RESET_COLOR: &str = "\x1b[0m";
+
+/// Draws `times` as a connected line into `buf`, where each braille dot row is `pt_height` above
+/// the last, starting from `min`
+fn draw_series(times: &[Duration], min: Duration, pt_height: Duration, width: usize, buf: &mut [Vec<char>]) {
     let horiz_size = width as f32  / times.len() as f32;
     for (i, times) in times.windows(2).enumerate() {
         let y1_pt = (times[0] - min).num_milliseconds() / pt_height.num_milliseconds();
         let y2_pt = (times[1] - min).num_milliseconds() / pt_height.num_milliseconds();
         
-        plot_line(((i as f32 * horiz_size) as i64 * 2, y1_pt), (((i + 1) as f32 * horiz_size) as i64 * 2, y2_pt), &mut buf);
-        
+        plot_line(((i as f32 * horiz_size) as i64 * 2, y1_pt), (((i + 1) as f32 * horiz_size) as i64 * 2, y2_pt), buf);
+    }
+}
+
+/// Labels the first day and the start of each month under the column its point is drawn in,
+/// skipping any label that would run into the one before it or off the end of the plot
+fn x_axis(dates: &[NaiveDate], width: usize) -> String {
+    let mut axis = String::new();
+    let mut prev_month = None;
+    for (i, date) in dates.iter().enumerate() {
+        let month = (date.year(), date.month());
+        if prev_month == Some(month) {
+            continue;
+        }
+        prev_month = Some(month);
+        let col = i * width / dates.len();
+        let label = date.format("%b").to_string();
+        if (!axis.is_empty() && axis.len() >= col) || col + label.len() > width {
+            continue;
+        }
+        axis.push_str(&" ".repeat(col - axis.len()));
+        axis.push_str(&label);
+    }
+    axis
+}
+
+fn render_series(label: &str, dates: &[NaiveDate], series: &[(&str, &[DateTime<FixedOffset>])], style: PlotStyle, color: bool) -> Vec<String> {
+    // Plotted as time since midnight, and labelled as the time of day
+    let midnight = NaiveTime::from_hms_opt(0, 0, 0).expect("Midnight is a valid time");
+    let series: Vec<_> = series.iter()
+        .map(|(name, times)| (*name, times.iter().map(|dt| dt.time() - midnight).collect::<Vec<_>>()))
+        .collect();
+    let format = |value: Duration| (midnight + value).format("%H:%M:%S").to_string();
+    render_values(label, dates, &series, format, style, color)
+}
+
+/// The lowest value across all of `series`, and the span of the y axis up from it to the highest.
+/// Flat (or nearly flat) data gets at least a minute per row, so each braille dot covers a
+/// nonzero span of time. There must be at least one value.
+fn value_range(series: &[(&str, Vec<Duration>)], rows: usize) -> (Duration, Duration) {
+    let min = *series.iter().flat_map(|(_, times)| times).min().unwrap();
+    let max = *series.iter().flat_map(|(_, times)| times).max().unwrap();
+    (min, (max - min).max(Duration::minutes(rows as i64)))
+}
+
+/// Renders each series as a line on a shared y axis, with labelled rows of the axis formatted
+/// by `format`
+fn render_values(
+    label: &str,
+    dates: &[NaiveDate],
+    series: &[(&str, Vec<Duration>)],
+    format: impl Fn(Duration) -> String,
+    style: PlotStyle,
+    color: bool,
+) -> Vec<String> {
+    let PlotStyle { width, height, glyphs, grid, .. } = style;
+    if width == 0 || height == 0 {
+        return vec![format!("{label}: not enough room to plot")];
     }
-    for (i, row) in buf.into_iter().enumerate() {
-        let row_tag = if i == 1 {
-            max.format("%H:%M:%S").to_string()
-        } else if i == height {
-            min.format("%H:%M:%S").to_string()
+    let series: Vec<_> = series.iter()
+        .map(|(name, times)| (*name, downsample(times.clone(), width)))
+        .collect();
+    if series.iter().all(|(_, times)| times.len() < 2) {
+        return vec![format!("{label}: not enough data to plot")];
+    }
+    let (min, duration) = value_range(&series, height);
+    let row_height = duration / height as i32;
+    let pt_height = row_height / 4;
+    let bufs: Vec<_> = series.iter().map(|(_, times)| {
+        let mut buf = vec![vec![' '; width]; height + 1];
+        draw_series(times, min, pt_height, width, &mut buf);
+        buf
+    }).collect();
+
+    // Row `i` holds points from `height - i` rows above the minimum, so the top row is the
+    // axis maximum, which can sit a little above the data's. The ends take priority over the
+    // label when there are too few rows for all three, and the label over a gridline's value.
+    let row_time = |i: usize| min + row_height * (height - i) as i32;
+    let mut rows: Vec<String> = (0..=height).map(|i| {
+        let on_grid = grid && (height - i) % GRID_SPACING == 0;
+        let row_tag = if i == 0 || i == height {
+            format(row_time(i))
         } else if i == height / 2 {
             label.to_string()
+        } else if on_grid {
+            format(row_time(i))
         } else {
             "".to_string()
         };
-        println!("{:>10} {}", row_tag, row.into_iter().collect::<String>());
+        let cells: String = (0..width).map(|col| {
+            let drawn: Vec<_> = bufs.iter().enumerate()
+                .filter(|(_, buf)| buf[i][col] != ' ')
+                .collect();
+            let pattern = drawn.iter().fold(0, |pattern, (_, buf)| pattern | pattern_from_char(buf[i][col]));
+            let cell = if pattern == 0 && on_grid { glyphs.grid() } else { glyphs.glyph(pattern) };
+            match drawn.as_slice() {
+                [(series, _)] if color => format!("{}{cell}{RESET_COLOR}", SERIES_COLORS[series % SERIES_COLORS.len()]),
+                _ => cell.to_string(),
+            }
+        }).collect();
+        format!("{:>10} {}", row_tag, cells)
+    }).collect();
+    if !dates.is_empty() {
+        rows.push(format!("{:>10} {}", "", x_axis(dates, width)));
+    }
+    if series.len() > 1 {
+        let legend: Vec<_> = series.iter().enumerate().map(|(i, (name, _))| {
+            if color {
+                format!("{}{}{RESET_COLOR} {name}", SERIES_COLORS[i % SERIES_COLORS.len()], glyphs.glyph(0xff))
+            } else {
+                format!("{} {name}", i + 1)
+            }
+        }).collect();
+        rows.push(format!("{:>10} {}", "", legend.join("  ")));
+    }
+    rows
+}
+
+#[cfg(test)]
+fn test_style(width: usize, height: usize) -> PlotStyle {
+    PlotStyle { width, height, glyphs: Glyphs::Braille, grid: false, color: false }
+}
+
+#[test]
+fn test_x_axis() {
+    let start = NaiveDate::from_ymd_opt(2023, 1, 1).unwrap();
+    let year: Vec<_> = (0..365).map(|day| start + Duration::days(day)).collect();
+    let axis = x_axis(&year, 73);
+    assert!(axis.starts_with("Jan   Feb  Mar"));
+    assert_eq!(axis.split_whitespace().count(), 12);
+    assert_eq!(axis.find("Jul"), Some(181 * 73 / 365));
+
+    // Labels too close to the one before are dropped rather than overlapping it
+    let axis = x_axis(&year, 24);
+    assert!(axis.split_whitespace().count() < 12);
+    assert!(axis.len() <= 24);
+
+    let late_jan: Vec<_> = (0..10).map(|day| start + Duration::days(day + 25)).collect();
+    assert_eq!(x_axis(&late_jan, 10), "Jan   Feb");
+}
+
+#[test]
+fn test_plot_grid() {
+    let start = DateTime::parse_from_rfc3339("2022-01-01T06:00:00+00:00").unwrap();
+    let times: Vec<_> = (0..10).map(|day| start + Duration::days(day) + Duration::minutes(day)).collect();
+    let style = PlotStyle { grid: true, ..test_style(10, 20) };
+    let rows = render_times("Test", &[], &times, style);
+    let gutter: Vec<_> = rows.iter().map(|row| row[..10].trim_start()).collect();
+    assert_eq!(gutter[0], "06:20:00");
+    assert_eq!(gutter[4], "");
+    assert_eq!(gutter[5], "06:15:00");
+    assert_eq!(gutter[10], "Test");
+    assert_eq!(gutter[15], "06:05:00");
+    assert_eq!(gutter[20], "06:00:00");
+    assert!(rows[5].contains(Glyphs::Braille.grid()));
+    assert!(!rows[4].contains(Glyphs::Braille.grid()));
+}
+
+#[test]
+fn test_render_series_shared_axis() {
+    let start = DateTime::parse_from_rfc3339("2022-01-01T06:00:00+00:00").unwrap();
+    let sunrises: Vec<_> = (0..10).map(|day| start + Duration::days(day)).collect();
+    let sunsets: Vec<_> = sunrises.iter().map(|dt| *dt + Duration::hours(12)).collect();
+    let rows = render_series("Sun", &[], &[("Sunrises", &sunrises[..]), ("Sunsets", &sunsets[..])], test_style(10, 4), false);
+    // Both series share one axis, from the earliest sunrise to the latest sunset
+    assert_eq!(rows.len(), 6);
+    assert_eq!(rows[0][..10].trim_start(), "18:00:00");
+    assert_eq!(rows[4][..10].trim_start(), "06:00:00");
+    assert!(rows[0].chars().skip(11).any(|ch| ch != ' '));
+    assert!(rows[4].chars().skip(11).any(|ch| ch != ' '));
+    assert!(rows[5].ends_with("1 Sunrises  2 Sunsets"));
+
+    let colored = render_series("Sun", &[], &[("Sunrises", &sunrises[..]), ("Sunsets", &sunsets[..])], test_style(10, 4), true);
+    assert!(colored[0].contains(SERIES_COLORS[1]));
+    assert!(colored[4].contains(SERIES_COLORS[0]));
+}
+
+#[test]
+fn test_plot_too_little_data() {
+    let time = DateTime::parse_from_rfc3339("2022-01-01T06:00:00+00:00").unwrap();
+    assert_eq!(render_times("Sunrises", &[], &[], test_style(10, 4)), vec!["Sunrises: not enough data to plot"]);
+    assert_eq!(render_times("Sunrises", &[], &[time], test_style(10, 4)), vec!["Sunrises: not enough data to plot"]);
+    plot_times(&mut io::sink(), "Sunrises", &[], &[time], test_style(10, 4)).unwrap();
+
+    // Identical times get a small axis rather than a zero-height one
+    let rows = render_times("Sunrises", &[], &[time, time], test_style(10, 4));
+    assert_eq!(rows.len(), 5);
+    assert_eq!(rows[0][..10].trim_start(), "06:04:00");
+    assert_eq!(rows[4][..10].trim_start(), "06:00:00");
+    assert!(rows[4].chars().skip(11).any(|ch| ch != ' '));
+    plot_times(&mut io::sink(), "Sunrises", &[], &[time, time], test_style(10, 4)).unwrap();
+}
+
+#[test]
+fn test_plot_no_room() {
+    let start = DateTime::parse_from_rfc3339("2022-01-01T06:00:00+00:00").unwrap();
+    let times: Vec<_> = (0..10).map(|day| start + Duration::days(day)).collect();
+    assert_eq!(render_times("Sunrises", &[], &times, test_style(10, 0)), vec!["Sunrises: not enough room to plot"]);
+    assert_eq!(render_times("Sunrises", &[], &times, test_style(0, 4)), vec!["Sunrises: not enough room to plot"]);
+    plot_times(&mut io::sink(), "Sunrises", &[], &times, test_style(10, 0)).unwrap();
+}
+
+#[test]
+fn test_render_durations() {
+    let lengths: Vec<_> = (0..10).map(|day| Duration::hours(9) + Duration::minutes(day * 10)).collect();
+    let rows = render_durations("Day length", &[], &lengths, test_style(10, 4));
+    let gutter: Vec<_> = rows.iter().map(|row| row[..10].trim_start()).collect();
+    assert_eq!(gutter, vec!["10:30", "", "Day length", "", "9:00"]);
+}
+
+#[test]
+fn test_plot_more_points_than_columns() {
+    let start = DateTime::parse_from_rfc3339("2022-01-01T06:00:00+00:00").unwrap();
+    let times: Vec<_> = (0..365)
+        .map(|day| start + Duration::days(day) + Duration::minutes(day * 120 / 365))
+        .collect();
+    let rows = render_times("Test", &[], &times, test_style(40, 10));
+    assert_eq!(rows.len(), 11);
+    for row in &rows {
+        assert_eq!(row.chars().count(), 11 + 40);
+    }
+    // One segment starts in every column; the final point only ends the last segment
+    for col in 0..39 {
+        assert!(rows.iter().any(|row| row.chars().nth(11 + col) != Some(' ')), "{col}");
+    }
+}
+
+#[test]
+fn test_plot_gutter_labels() {
+    let start = DateTime::parse_from_rfc3339("2022-01-01T06:00:00+00:00").unwrap();
+    let times: Vec<_> = (0..3)
+        .map(|day| start + Duration::days(day) + Duration::hours(day))
+        .collect();
+    let rows = render_times("Test", &[], &times, test_style(10, 4));
+    let gutter: Vec<_> = rows.iter().map(|row| row[..10].trim_start()).collect();
+    assert_eq!(gutter, vec!["08:00:00", "", "Test", "", "06:00:00"]);
+
+    // With only two rows there's no room for the label between the ends
+    let rows = render_times("Test", &[], &times, test_style(10, 1));
+    let gutter: Vec<_> = rows.iter().map(|row| row[..10].trim_start()).collect();
+    assert_eq!(gutter, vec!["08:00:00", "06:00:00"]);
+}
+
+/// Pixels per character of `PlotStyle::width` and `height` in SVG output, so a plot keeps
+/// roughly the proportions it has in a terminal
+const SVG_COLUMN: f64 = 8.;
+const SVG_ROW: f64 = 24.;
+/// Space around the plot area for the axis labels, title and legend
+const SVG_LEFT: f64 = 70.;
+const SVG_RIGHT: f64 = 20.;
+const SVG_TOP: f64 = 30.;
+const SVG_BOTTOM: f64 = 50.;
+const SVG_COLORS: [&str; 4] = ["#d4a017", "#c0392b", "#2980b9", "#8e44ad"];
+
+/// Like `plot_times_multi`, but as an SVG document with each series drawn as a line
+pub fn plot_times_svg(label: &str, dates: &[NaiveDate], series: &[(&str, &[DateTime<FixedOffset>])], style: PlotStyle) -> String {
+    let midnight = NaiveTime::from_hms_opt(0, 0, 0).expect("Midnight is a valid time");
+    let series: Vec<_> = series.iter()
+        .map(|(name, times)| (*name, times.iter().map(|dt| dt.time() - midnight).collect::<Vec<_>>()))
+        .collect();
+    render_svg(label, dates, &series, |value| (midnight + value).format("%H:%M").to_string(), style)
+}
+
+/// Like `plot_durations`, but as an SVG document
+pub fn plot_durations_svg(label: &str, dates: &[NaiveDate], values: &[Duration], style: PlotStyle) -> String {
+    let format = |value: Duration| format!("{}:{:02}", value.num_hours(), value.num_minutes() % 60);
+    render_svg(label, dates, &[(label, values.to_vec())], format, style)
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// Draws each series as a `<polyline>` over a shared y axis labelled by `format` at the bottom,
+/// middle and top (and every `GRID_SPACING` rows with `style.grid`), with the start of each month
+/// marked along the x axis as in the terminal plot
+fn render_svg(
+    label: &str,
+    dates: &[NaiveDate],
+    series: &[(&str, Vec<Duration>)],
+    format: impl Fn(Duration) -> String,
+    style: PlotStyle,
+) -> String {
+    let (plot_width, plot_height) = (style.width as f64 * SVG_COLUMN, style.height as f64 * SVG_ROW);
+    let (width, height) = (SVG_LEFT + plot_width + SVG_RIGHT, SVG_TOP + plot_height + SVG_BOTTOM);
+    let bottom = SVG_TOP + plot_height;
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{height}\" viewBox=\"0 0 {width} {height}\" font-family=\"sans-serif\" font-size=\"12\">\n"
+    );
+    svg.push_str(&format!("<text x=\"{}\" y=\"20\" text-anchor=\"middle\" font-size=\"14\">{}</text>\n", SVG_LEFT + plot_width / 2., escape_xml(label)));
+    if series.iter().all(|(_, values)| values.len() < 2) {
+        svg.push_str(&format!("<text x=\"{}\" y=\"{}\" text-anchor=\"middle\">Not enough data to plot</text>\n", SVG_LEFT + plot_width / 2., SVG_TOP + plot_height / 2.));
+        svg.push_str("</svg>\n");
+        return svg;
+    }
+    let (min, duration) = value_range(series, style.height);
+    let y = |value: Duration| bottom - (value - min).num_milliseconds() as f64 / duration.num_milliseconds() as f64 * plot_height;
+
+    // Axes, then the y axis labels, with a gridline at each if asked for
+    svg.push_str(&format!("<path d=\"M {SVG_LEFT} {SVG_TOP} V {bottom} H {}\" stroke=\"black\" fill=\"none\"/>\n", SVG_LEFT + plot_width));
+    let mut label_rows = vec![0, style.height / 2, style.height];
+    if style.grid {
+        label_rows.extend((0..=style.height).step_by(GRID_SPACING));
+    }
+    label_rows.sort_unstable();
+    label_rows.dedup();
+    for row in label_rows {
+        let value = min + duration * row as i32 / style.height.max(1) as i32;
+        let y = y(value);
+        svg.push_str(&format!("<text x=\"{}\" y=\"{y:.1}\" text-anchor=\"end\" dominant-baseline=\"middle\">{}</text>\n", SVG_LEFT - 6., escape_xml(&format(value))));
+        if style.grid {
+            svg.push_str(&format!("<line x1=\"{SVG_LEFT}\" y1=\"{y:.1}\" x2=\"{}\" y2=\"{y:.1}\" stroke=\"#ddd\"/>\n", SVG_LEFT + plot_width));
+        }
+    }
+
+    // Each point is placed by its index, spreading the series across the full width
+    let x = |i: usize, count: usize| SVG_LEFT + i as f64 * plot_width / (count.max(2) - 1) as f64;
+    let mut prev_month = None;
+    for (i, date) in dates.iter().enumerate() {
+        let month = (date.year(), date.month());
+        if prev_month == Some(month) {
+            continue;
+        }
+        prev_month = Some(month);
+        let x = x(i, dates.len());
+        svg.push_str(&format!("<line x1=\"{x:.1}\" y1=\"{bottom}\" x2=\"{x:.1}\" y2=\"{}\" stroke=\"black\"/>\n", bottom + 5.));
+        svg.push_str(&format!("<text x=\"{x:.1}\" y=\"{}\">{}</text>\n", bottom + 18., date.format("%b")));
     }
+
+    for (i, (name, values)) in series.iter().enumerate() {
+        let color = SVG_COLORS[i % SVG_COLORS.len()];
+        let points: Vec<_> = values.iter().enumerate()
+            .map(|(j, value)| format!("{:.1},{:.1}", x(j, values.len()), y(*value)))
+            .collect();
+        svg.push_str(&format!("<polyline points=\"{}\" stroke=\"{color}\" stroke-width=\"2\" fill=\"none\"><title>{}</title></polyline>\n", points.join(" "), escape_xml(name)));
+        if series.len() > 1 {
+            svg.push_str(&format!("<text x=\"{}\" y=\"{}\" fill=\"{color}\">{}</text>\n", SVG_LEFT + i as f64 * 120., height - 10., escape_xml(name)));
+        }
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+#[test]
+fn test_plot_times_svg() {
+    let start = DateTime::parse_from_rfc3339("2022-01-30T06:00:00+00:00").unwrap();
+    let sunrises: Vec<_> = (0..10).map(|day| start + Duration::days(day) - Duration::minutes(day)).collect();
+    let sunsets: Vec<_> = (0..10).map(|day| start + Duration::days(day) + Duration::hours(12)).collect();
+    let dates: Vec<_> = sunrises.iter().map(|dt| dt.naive_local().date()).collect();
+    let svg = plot_times_svg("Sun & more", &dates, &[("Sunrises", &sunrises[..]), ("Sunsets", &sunsets[..])], test_style(10, 4));
+    assert!(svg.starts_with("<svg ") && svg.ends_with("</svg>\n"));
+    assert_eq!(svg.matches("<polyline ").count(), 2);
+    assert!(svg.contains(">Sun &amp; more</text>"));
+    // The axis runs from the earliest sunrise to the latest sunset, and the months are marked
+    assert!(svg.contains(">05:51</text>") && svg.contains(">18:00</text>"), "{svg}");
+    assert!(svg.contains(">Jan</text>") && svg.contains(">Feb</text>"));
+    assert!(svg.contains(">Sunrises</text>") && svg.contains(">Sunsets</text>"));
+    // The earliest sunrise is at the bottom of the plot area, the latest sunset at the top
+    let bottom = SVG_TOP + 4. * SVG_ROW;
+    assert!(svg.contains(&format!("{:.1},{bottom:.1}\"", SVG_LEFT + 10. * SVG_COLUMN)));
+    assert!(svg.contains(&format!("{SVG_LEFT:.1},{SVG_TOP:.1} ")));
+
+    let empty = plot_durations_svg("Day length", &[], &[Duration::hours(9)], test_style(10, 4));
+    assert!(empty.contains("Not enough data to plot") && !empty.contains("<polyline"));
+}
+
+const SPARK_LEVELS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// Renders a series as a single row of block characters, one per value, scaled between the
+/// series' minimum and maximum. A flat series (including a single day) is drawn at mid height.
+pub fn sparkline(values: &[Duration]) -> String {
+    let (min, max) = match (values.iter().min(), values.iter().max()) {
+        (Some(min), Some(max)) => (*min, *max),
+        _ => return String::new(),
+    };
+    let range = (max - min).num_milliseconds();
+    let top = SPARK_LEVELS.len() as i64 - 1;
+    values
+        .iter()
+        .map(|value| {
+            let level = if range == 0 {
+                top / 2
+            } else {
+                ((*value - min).num_milliseconds() * top + range / 2) / range
+            };
+            SPARK_LEVELS[level as usize]
+        })
+        .collect()
+}
+
+#[test]
+fn test_sparkline() {
+    assert_eq!(sparkline(&[]), "");
+    assert_eq!(sparkline(&[Duration::hours(12)]), "▄");
+    assert_eq!(sparkline(&[Duration::hours(12), Duration::hours(12)]), "▄▄");
+    assert_eq!(
+        sparkline(&[Duration::hours(8), Duration::hours(12), Duration::hours(16)]),
+        "▁▅█"
+    );
+}
+
+/// Draws an equirectangular map of the globe, north at the top and the antimeridian at either
+/// edge, shading each cell by the sun's elevation at its centre as given by `elevation_at(lat,
+/// long)`: full blocks where the sun is up, medium shade during civil twilight, light shade at
+/// night. Resolution is capped at half a degree per cell.
+pub fn plot_daylight_map(
+    width: usize,
+    height: usize,
+    elevation_at: impl Fn(f64, f64) -> f64,
+) -> Vec<String> {
+    let width = width.clamp(1, 720);
+    let height = height.clamp(1, 360);
+    (0..height)
+        .map(|row| {
+            let lat = 90. - (row as f64 + 0.5) * 180. / height as f64;
+            (0..width)
+                .map(|col| {
+                    let long = -180. + (col as f64 + 0.5) * 360. / width as f64;
+                    match elevation_at(lat, long) {
+                        elevation if elevation > 0. => '█',
+                        elevation if elevation > -6. => '▒',
+                        _ => '░',
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+#[test]
+fn test_plot_daylight_map() {
+    let rows = plot_daylight_map(8, 4, |lat, _| lat / 10.);
+    assert_eq!(rows, vec!["████████", "████████", "▒▒▒▒▒▒▒▒", "░░░░░░░░"]);
 }
@@ -0,0 +1,154 @@
+//! Sunrise and sunset on plain numbers, without `chrono` or `std`, for embedded targets. This is
+//! all that's left of the crate when it's built with `default-features = false`.
+//!
+//! Times are in minutes after midnight UTC on the given day, and can fall outside `0..1440` when
+//! the event is on the UTC day before or after.
+
+use core::f64::consts::TAU;
+
+use num_traits::Float;
+
+/// Zenith angle of the sun's centre at sunrise and sunset, allowing for refraction and the
+/// sun's apparent radius
+pub(crate) const SUNRISE_ZENITH: f64 = 90.883;
+
+/// Converts one of the constants here to the float type in use. They're all well within the
+/// range of `f32`.
+fn lit<T: Float>(value: f64) -> T {
+  T::from(value).expect("Constants fit in any float type")
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub(crate) struct FractionalYear<T = f64>(pub(crate) T);
+impl<T: Float> FractionalYear<T> {
+  pub fn sin(self) -> T { self.0.sin() }
+  pub fn two_sin(self) -> T { (self.0 * lit(2.)).sin() }
+  pub fn three_sin(self) -> T { (self.0 * lit(3.)).sin() }
+
+  pub fn cos(self) -> T { self.0.cos() }
+  pub fn two_cos(self) -> T { (self.0 * lit(2.)).cos() }
+  pub fn three_cos(self) -> T { (self.0 * lit(3.)).cos() }
+}
+
+/// Equation of time
+/// Returns the amount that actual solar time differs from ideal solar time at a given point in the year:
+/// https://en.wikipedia.org/wiki/Equation_of_time
+pub(crate) fn eqtime<T: Float>(gamma: FractionalYear<T>) -> T {
+  let c = lit::<T>;
+  c(229.18) * (c(0.000_075)
+      + c(0.001_868) * gamma.cos() - c(0.032_077) * gamma.sin()
+      - c(0.014_615) * gamma.two_cos() - c(0.040_849) * gamma.two_sin())
+}
+
+/// Returns the solar declention angle for a given fractional year
+pub(crate) fn decl<T: Float>(gamma: FractionalYear<T>) -> T {
+  let c = lit::<T>;
+  c(0.006_918)
+      - c(0.399_912) * gamma.cos() + c(0.070_257) * gamma.sin()
+      - c(0.006_758) * gamma.two_cos() + c(0.000_907) * gamma.two_sin()
+      - c(0.002_697) * gamma.three_cos() + c(0.001_480) * gamma.three_sin()
+}
+
+pub(crate) fn days_in_year(year: i32) -> f64 {
+  let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+  if leap { 366. } else { 365. }
+}
+
+/// Cosine of the hour angle at which the sun is at `zenith` degrees, given the latitude and
+/// declination in radians. Outside `-1..=1`, the sun never gets that high (above 1) or that low
+/// (below -1).
+pub(crate) fn cos_zenith_hour_angle<T: Float>(lat: T, decl: T, zenith: T) -> T {
+  zenith.to_radians().cos() / (lat.cos() * decl.cos()) - lat.tan() * decl.tan()
+}
+
+/// Minutes after the day starts at the prime meridian that the sun crosses the meridian at
+/// `long` degrees east, on a planet whose solar day is `day_minutes` long (1440 on Earth)
+pub(crate) fn noon_minutes<T: Float>(day_minutes: T, long: T, eqtime: T) -> T {
+  day_minutes / lit(2.) - day_minutes / lit(360.) * long - eqtime
+}
+
+/// Minutes either side of noon that the hour angle with cosine `cos_ha` comes round, on a planet
+/// whose solar day is `day_minutes` long
+pub(crate) fn event_offset<T: Float>(day_minutes: T, cos_ha: T) -> T {
+  day_minutes / lit(360.) * cos_ha.acos().to_degrees()
+}
+
+/// Sunrise (if `rising`) or sunset, computed with the declination and equation of time at
+/// `minutes` past midnight UTC
+fn event_at<T: Float>(
+  lat: T,
+  long: T,
+  ordinal_day: u16,
+  year: i32,
+  minutes: T,
+  rising: bool,
+) -> Option<T> {
+  let c = lit::<T>;
+  let day = c(ordinal_day as f64 - 1.) + (minutes / c(60.) - c(12.)) / c(24.);
+  let gamma = FractionalYear(day / c(days_in_year(year)) * c(TAU));
+  let cos_ha = cos_zenith_hour_angle(lat.to_radians(), decl(gamma), c(SUNRISE_ZENITH));
+  if !(c(-1.)..=c(1.)).contains(&cos_ha) {
+    return None;
+  }
+  let offset = event_offset(c(1440.), cos_ha);
+  let noon = noon_minutes(c(1440.), long, eqtime(gamma));
+  Some(if rising { noon - offset } else { noon + offset })
+}
+
+/// Estimates the event from noon UTC, then recomputes it from the estimate, as `Pos::sunrise`
+/// does
+fn event_minutes<T: Float>(
+  lat: T,
+  long: T,
+  ordinal_day: u16,
+  year: i32,
+  rising: bool,
+) -> Option<T> {
+  let estimate = event_at(lat, long, ordinal_day, year, lit(720.), rising)?;
+  event_at(lat, long, ordinal_day, year, estimate, rising)
+}
+
+/// Minutes after midnight UTC of sunrise at `lat`, `long` (in degrees, positive north and east)
+/// on day `ordinal_day` of `year`, counting 1 January as day 1. `None` means the sun doesn't
+/// rise or set that day. This works in either `f64` or, where memory is tight, `f32`, which
+/// agrees to within a minute or so.
+pub fn sunrise_minutes<T: Float>(lat: T, long: T, ordinal_day: u16, year: i32) -> Option<T> {
+  event_minutes(lat, long, ordinal_day, year, true)
+}
+
+/// Minutes after midnight UTC of sunset, as for [`sunrise_minutes`]
+pub fn sunset_minutes<T: Float>(lat: T, long: T, ordinal_day: u16, year: i32) -> Option<T> {
+  event_minutes(lat, long, ordinal_day, year, false)
+}
+
+#[test]
+fn test_f32_matches_f64() {
+    for (lat, long) in [(51.5, -0.13), (-34.6, -58.4), (40.7, -74.)] {
+        for day in [1, 80, 172, 266, 355] {
+            let sunrise = sunrise_minutes(lat, long, day, 2023).unwrap();
+            let sunrise_f32 = sunrise_minutes(lat as f32, long as f32, day, 2023).unwrap();
+            assert!((sunrise - sunrise_f32 as f64).abs() < 2., "{lat} {day}: {sunrise_f32}");
+            let sunset = sunset_minutes(lat, long, day, 2023).unwrap();
+            let sunset_f32 = sunset_minutes(lat as f32, long as f32, day, 2023).unwrap();
+            assert!((sunset - sunset_f32 as f64).abs() < 2., "{lat} {day}: {sunset_f32}");
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_event_minutes_match_pos() {
+    use chrono::{DateTime, TimeZone, Utc};
+
+    let midnight = Utc.with_ymd_and_hms(2023, 5, 1, 0, 0, 0).unwrap();
+    let minutes = |time: DateTime<Utc>| (time - midnight).num_seconds() as f64 / 60.;
+    for (lat, long) in [(51.5, -0.13), (-34.6, -58.4), (40.7, -74.)] {
+        let pos = crate::Pos::new(lat, long);
+        let sunrise = sunrise_minutes(lat, long, 121, 2023).unwrap();
+        let sunset = sunset_minutes(lat, long, 121, 2023).unwrap();
+        assert!((sunrise - minutes(pos.sunrise(midnight))).abs() < 2., "{lat} {sunrise}");
+        assert!((sunset - minutes(pos.sunset(midnight))).abs() < 2., "{lat} {sunset}");
+    }
+    assert_eq!(sunrise_minutes(80., 0., 172, 2023), None);
+    assert_eq!(sunset_minutes(-80., 0., 172, 2023), None);
+}
@@ -0,0 +1,1749 @@
+use chrono::{Duration, prelude::*};
+use serde::{Deserialize, Serialize};
+use std::f64::consts::{TAU};
+use std::iter::FusedIterator;
+use std::ops::{Range, RangeInclusive};
+use std::str::FromStr;
+use thiserror::Error;
+
+use crate::raw::{self, days_in_year, decl, eqtime, FractionalYear, SUNRISE_ZENITH};
+
+/// Earth's fractional year at `dt`, counted through its actual calendar year so leap years don't
+/// drift a day out by December. The time of day counts down to the second, so the sun's position
+/// changes smoothly rather than in hourly steps.
+fn gamma(dt: DateTime<Utc>) -> FractionalYear {
+  let hours = dt.num_seconds_from_midnight() as f64 / 3600.;
+  let day = (dt.ordinal() - 1) as f64 + (hours - 12.) / 24.;
+  FractionalYear(day / days_in_year(dt.year()) * TAU)
+}
+
+/// How the sun's path through a planet's year is worked out
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OrbitModel {
+    /// NOAA's fitted series for Earth's equation of time and declination, with the position in
+    /// the year taken from the calendar
+    Earth,
+    /// A circular orbit: the equation of time is taken to be zero, and the sun is taken to move
+    /// through the year evenly, counted in local solar days from `Planet::solstice`
+    #[default]
+    Circular,
+}
+
+/// Rotation and orbital parameters of the body the observer is standing on.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Planet {
+    /// Length of a mean solar day, in Earth minutes
+    pub day_minutes: f64,
+    /// Length of a year, in local solar days. The Earth model instead takes its position in the
+    /// year from the calendar, with 366 days in leap years.
+    pub year_days: f64,
+    /// Axial tilt relative to the orbital plane, in degrees
+    pub obliquity: f64,
+    /// A moment a solar day began at the prime meridian, in seconds since the Unix epoch. Days
+    /// are counted in whole `day_minutes` from here. Defaults to the epoch itself, which is
+    /// midnight UTC.
+    #[serde(default)]
+    pub day_epoch: i64,
+    /// A northern winter solstice, in seconds since the Unix epoch, from which the circular
+    /// model counts the year. The Earth model takes its seasons from the calendar instead.
+    #[serde(default)]
+    pub solstice: i64,
+    /// Which model the sun's declination and equation of time come from. Planets serialized
+    /// without one use a circular orbit.
+    #[serde(default)]
+    pub model: OrbitModel,
+}
+
+impl Planet {
+    pub const EARTH: Planet = Planet {
+        day_minutes: 1440.,
+        year_days: 365.,
+        obliquity: 23.44,
+        day_epoch: 0,
+        solstice: 0,
+        model: OrbitModel::Earth,
+    };
+    /// Mars, with sols counted as Mars Coordinated Time does: midnight at the prime meridian is
+    /// a whole Mars Sol Date, the first after the Unix epoch being 1969-12-31 16:42:16 UTC. Its
+    /// northern winter solstice (Ls 270°) was on 2022-07-21 at 06:11 UTC.
+    pub const MARS: Planet = Planet {
+        day_minutes: 1479.587,
+        year_days: 668.59,
+        obliquity: 25.19,
+        day_epoch: -26_264,
+        solstice: 1_658_383_866,
+        model: OrbitModel::Circular,
+    };
+
+    fn is_earth(self) -> bool {
+        self.model == OrbitModel::Earth
+    }
+
+    /// Minutes of local time per degree of longitude
+    fn minutes_per_degree(self) -> f64 {
+        self.day_minutes / 360.
+    }
+
+    /// Minutes from `since` (in seconds since the Unix epoch) to `dt`
+    fn minutes_since(dt: DateTime<Utc>, since: i64) -> f64 {
+        (dt.timestamp_millis() - since * 1000) as f64 / 60_000.
+    }
+
+    /// Minutes since the start of the solar day containing `dt`, at the prime meridian
+    fn minutes_into_day(self, dt: DateTime<Utc>) -> f64 {
+        Planet::minutes_since(dt, self.day_epoch).rem_euclid(self.day_minutes)
+    }
+
+    /// Start of the solar day containing `dt` at the prime meridian: a whole number of days of
+    /// `day_minutes` since `day_epoch`, as `Pos::hour_angle` counts them. On Earth this is
+    /// midnight UTC; a Martian sol starts about 40 minutes later each Earth day.
+    fn day_start(self, dt: DateTime<Utc>) -> DateTime<Utc> {
+        let minutes = self.minutes_into_day(dt);
+        dt - Duration::milliseconds((minutes * 60_000.).round() as i64)
+    }
+
+    fn gamma(self, dt: DateTime<Utc>) -> FractionalYear {
+        if self.is_earth() {
+            gamma(dt)
+        } else {
+            let days = Planet::minutes_since(dt, self.solstice) / self.day_minutes;
+            FractionalYear(days.rem_euclid(self.year_days) / self.year_days * TAU)
+        }
+    }
+
+    fn eqtime(self, gamma: FractionalYear) -> f64 {
+        if self.is_earth() {
+            eqtime(gamma)
+        } else {
+            0.
+        }
+    }
+
+    fn decl(self, gamma: FractionalYear) -> f64 {
+        if self.is_earth() {
+            decl(gamma)
+        } else {
+            -(self.obliquity.to_radians().sin() * gamma.cos()).asin()
+        }
+    }
+
+    fn params(self, dt: DateTime<Utc>) -> SolarParams {
+        let gamma = self.gamma(dt);
+        SolarParams {
+            decl: self.decl(gamma),
+            eqtime: self.eqtime(gamma),
+        }
+    }
+}
+
+/// The date-dependent quantities every event calculation needs, so that several events on the
+/// same day can share them
+#[derive(Debug, Clone, Copy)]
+struct SolarParams {
+    /// Solar declination, in radians
+    decl: f64,
+    /// Equation of time, in minutes
+    eqtime: f64,
+}
+
+const CIVIL_ZENITH: f64 = 96.;
+const NAUTICAL_ZENITH: f64 = 102.;
+const ASTRONOMICAL_ZENITH: f64 = 108.;
+
+/// Mean radius of the Earth, for distances between positions
+const EARTH_RADIUS_KM: f64 = 6371.0088;
+
+/// The most `Pos::shadow_ratio` returns: the ratio for a sun about 0.57° up. Lower than that the
+/// ratio heads off to infinity, and refraction has moved the sun further than the model is good to.
+pub const MAX_SHADOW_RATIO: f64 = 100.;
+
+impl Default for Planet {
+    fn default() -> Self {
+        Planet::EARTH
+    }
+}
+
+
+
+/// The instant `minutes` after `day_start`, truncated to the second. Far enough east or west,
+/// events fall on the day before or after, giving minutes below zero or past a whole day.
+fn minutes_after(day_start: DateTime<Utc>, minutes: f64) -> DateTime<Utc> {
+  day_start + Duration::seconds((minutes * 60.).floor() as i64)
+}
+
+/// An observer's position. Serialized as `lat` and `long` in degrees, plus `planet` and
+/// `elevation`, which default to Earth and sea level when missing. Deserializing checks the
+/// coordinates as `Pos::try_new` does.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(try_from = "PosFields")]
+pub struct Pos {
+    lat: f64,
+    long: f64,
+    planet: Planet,
+    /// Observer's height above sea level, in metres
+    elevation: f64,
+}
+
+/// `Pos` as it's serialized, before its coordinates are checked
+#[derive(Deserialize)]
+struct PosFields {
+    lat: f64,
+    long: f64,
+    #[serde(default)]
+    planet: Planet,
+    #[serde(default)]
+    elevation: f64,
+}
+
+impl TryFrom<PosFields> for Pos {
+    type Error = PosError;
+
+    fn try_from(fields: PosFields) -> Result<Self, Self::Error> {
+        let pos = Pos::try_new(fields.lat, fields.long)?;
+        Ok(Pos { planet: fields.planet, elevation: fields.elevation, ..pos })
+    }
+}
+
+impl Pos {
+    /// A position at `lat` degrees north and `long` degrees east. Longitudes are wrapped into
+    /// `(-180, 180]`, so 185° east is 175° west and 180° west is 180° east, and latitudes are
+    /// clamped to the poles.
+    pub fn new(lat: f64, long: f64) -> Self {
+        let long = if long > -180. && long <= 180. {
+            long
+        } else {
+            180. - (180. - long).rem_euclid(360.)
+        };
+        Pos { lat: lat.clamp(-90., 90.), long, planet: Planet::EARTH, elevation: 0. }
+    }
+
+    /// Like `new`, for an observer `meters` above sea level. From higher up the horizon dips
+    /// below the horizontal, so the sun rises earlier and sets later.
+    pub fn with_elevation(lat: f64, long: f64, meters: f64) -> Self {
+        Pos { elevation: meters, ..Pos::new(lat, long) }
+    }
+
+    /// Like `new`, but rejects a latitude beyond ±90° (often a sign of transposed coordinates)
+    /// or a longitude that isn't finite rather than clamping it. Other longitudes are wrapped.
+    pub fn try_new(lat: f64, long: f64) -> Result<Self, PosError> {
+        if (-90. ..=90.).contains(&lat) && long.is_finite() {
+            Ok(Pos::new(lat, long))
+        } else {
+            Err(PosError::OutOfRange(lat, long))
+        }
+    }
+
+    /// Latitude in degrees, positive to the north
+    pub fn lat(self) -> f64 {
+        self.lat
+    }
+
+    /// Longitude in degrees, positive to the east
+    pub fn long(self) -> f64 {
+        self.long
+    }
+
+    /// Projects this position onto an equirectangular map, returning `(x, y)` in `[0, 1]` with
+    /// `(0, 0)` at the top-left (north-west) corner and `(1, 1)` at the bottom-right. The
+    /// antimeridian is 180° east, so it's on the right-hand edge.
+    pub fn equirectangular(self) -> (f64, f64) {
+        ((self.long + 180.) / 360., (90. - self.lat) / 180.)
+    }
+
+    /// Great-circle distance to `other` in kilometres, by the haversine formula on a spherical
+    /// Earth of mean radius. Good to about half a percent against the true ellipsoid.
+    pub fn distance_km(self, other: Pos) -> f64 {
+        let (lat1, lat2) = (self.lat.to_radians(), other.lat.to_radians());
+        let half_dlat = (lat2 - lat1) / 2.;
+        let half_dlong = (other.long - self.long).to_radians() / 2.;
+        let a = half_dlat.sin().powi(2) + lat1.cos() * lat2.cos() * half_dlong.sin().powi(2);
+        2. * EARTH_RADIUS_KM * a.sqrt().min(1.).asin()
+    }
+
+    /// Initial bearing of the great circle from here to `other`, in degrees clockwise from north
+    /// in `[0, 360)`. The bearing along a great circle changes as you go, except along the
+    /// equator or a meridian.
+    pub fn bearing_deg(self, other: Pos) -> f64 {
+        let (lat1, lat2) = (self.lat.to_radians(), other.lat.to_radians());
+        let dlong = (other.long - self.long).to_radians();
+        let y = dlong.sin() * lat2.cos();
+        let x = lat1.cos() * lat2.sin() - lat1.sin() * lat2.cos() * dlong.cos();
+        y.atan2(x).to_degrees().rem_euclid(360.)
+    }
+
+    /// Returns a copy of this position on a different planet. Times are still
+    /// reported as instants on Earth's clock.
+    pub fn with_planet(self, planet: Planet) -> Self {
+        Pos { planet, ..self }
+    }
+
+    /// Solar noon on the day starting at `day_start`, using the equation of time at `estimate`.
+    /// The equation of time drifts by up to half a minute a day, so the closer `estimate` is to
+    /// noon itself, the closer the result is too.
+    fn refine_noon(self, day_start: DateTime<Utc>, estimate: DateTime<Utc>) -> DateTime<Utc> {
+        let minutes = self.noon_minutes(self.planet.params(estimate));
+        minutes_after(day_start, minutes)
+    }
+
+    /// The moment the sun crosses the meridian on the day of `dt`: the UTC day on Earth, or the
+    /// sol on Mars, counted as `Planet::day_start` does.
+    ///
+    /// `dt` can be anywhere in the day, so the first pass may take the equation of time from
+    /// half a day away. Refining once more, from that first estimate, takes it from within a few
+    /// minutes of noon instead; a third pass wouldn't move the result by a whole second.
+    pub fn solar_noon(self, dt: DateTime<Utc>) -> DateTime<Utc> {
+        let day_start = self.planet.day_start(dt);
+        self.refine_noon(day_start, self.refine_noon(day_start, dt))
+    }
+
+    /// Morning (if `rising`) or evening crossing of `zenith` on the day starting at
+    /// `day_start`, using the declination and equation of time in `params`, or which way the sun
+    /// misses it if it never reaches that angle
+    fn event_with_params(
+        self,
+        day_start: DateTime<Utc>,
+        params: SolarParams,
+        zenith: f64,
+        rising: bool,
+    ) -> Result<DateTime<Utc>, SolarError> {
+        let cos_ha = self.cos_hour_angle_for_zenith(params, zenith);
+        if cos_ha > 1. {
+            return Err(SolarError::SunAlwaysDown);
+        } else if cos_ha < -1. {
+            return Err(SolarError::SunAlwaysUp);
+        }
+        let offset = raw::event_offset(self.planet.day_minutes, cos_ha);
+        let noon = self.noon_minutes(params);
+        let minutes = if rising { noon - offset } else { noon + offset };
+        Ok(minutes_after(day_start, minutes))
+    }
+
+    /// Finds the event at `zenith` on the day of `dt`, then recomputes it with the sun's
+    /// position at that estimate
+    fn refined_event(
+        self,
+        dt: DateTime<Utc>,
+        zenith: f64,
+        rising: bool,
+    ) -> Result<DateTime<Utc>, SolarError> {
+        let day_start = self.planet.day_start(dt);
+        let event = |at| self.event_with_params(day_start, self.planet.params(at), zenith, rising);
+        event(event(dt)?)
+    }
+
+    /// Like `refined_event`, but when the sun never crosses `zenith`, treats it as crossing at
+    /// solar noon (if it stays beyond `zenith`) or twelve hours either side of it (if it never
+    /// gets there), so the day's length comes out as zero or a full day.
+    fn _event_or_polar(self, dt: DateTime<Utc>, zenith: f64, rising: bool) -> DateTime<Utc> {
+        self.refined_event(dt, zenith, rising).unwrap_or_else(|err| {
+            let noon = self.solar_noon(dt);
+            let half_day = Duration::minutes((self.planet.day_minutes / 2.) as i64);
+            match (err, rising) {
+                (SolarError::SunAlwaysDown, _) => noon,
+                (SolarError::SunAlwaysUp, true) => noon - half_day,
+                (SolarError::SunAlwaysUp, false) => noon + half_day,
+            }
+        })
+    }
+
+    /// Returns the morning and evening times on the day of `dt` that the centre of the sun is
+    /// `zenith` degrees from straight up. This is measured from the zenith, not the horizon: 90°
+    /// is the geometric horizon, 90.883° is sunrise/sunset (allowing for refraction and the
+    /// sun's radius), and 96° is civil twilight. A panel that needs the sun 6° above the
+    /// horizon would use 84°. On days the sun never reaches the angle, both times are solar
+    /// noon if it stays below it, or twelve hours either side of noon if it stays above; see
+    /// `time_at_elevation` for a version that reports these days as `None`.
+    pub fn event_for_zenith(
+        self,
+        dt: DateTime<Utc>,
+        zenith: f64,
+    ) -> (DateTime<Utc>, DateTime<Utc>) {
+        (
+            self._event_or_polar(dt, zenith, true),
+            self._event_or_polar(dt, zenith, false),
+        )
+    }
+
+    /// Returns sunrise on the day of `dt`. During polar night this is solar noon, and during
+    /// polar day it's twelve hours before solar noon, so the day length comes out as zero or a
+    /// full day; use `try_sunrise` to tell these days apart.
+    pub fn sunrise(self, dt: DateTime<Utc>) -> DateTime<Utc> {
+        self._event_or_polar(dt, self.horizon_zenith(), true)
+    }
+
+    /// Returns sunset on the day of `dt`. As with `sunrise`, polar night gives solar noon and
+    /// polar day gives twelve hours after it; use `try_sunset` to tell these days apart.
+    pub fn sunset(self, dt: DateTime<Utc>) -> DateTime<Utc> {
+        self._event_or_polar(dt, self.horizon_zenith(), false)
+    }
+
+    /// Returns the first sunrise strictly after `after`, which is tomorrow's if today's has
+    /// passed. Days without a sunrise are skipped; `None` means there's none within a year.
+    pub fn next_sunrise(self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.next_event(after, |dt| self.try_sunrise(dt))
+    }
+
+    /// Returns the first sunset strictly after `after`, skipping days without one. `None` means
+    /// there's none within a year.
+    pub fn next_sunset(self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.next_event(after, |dt| self.try_sunset(dt))
+    }
+
+    fn next_event(
+        self,
+        after: DateTime<Utc>,
+        event: impl Fn(DateTime<Utc>) -> Result<DateTime<Utc>, SolarError>,
+    ) -> Option<DateTime<Utc>> {
+        // Start a day early, as the event for a UTC day can fall near either end of it
+        (-1..=366)
+            .map(|days| after + Duration::days(days))
+            .filter_map(|dt| event(dt).ok())
+            .find(|time| *time > after)
+    }
+
+    /// Returns how long the sun is up on the day of `dt`: a full day during polar day, and zero
+    /// during polar night
+    pub fn day_length(self, dt: DateTime<Utc>) -> Duration {
+        self.sunset(dt) - self.sunrise(dt)
+    }
+
+    /// Returns sunrise on the day of `dt`, or an error saying whether the sun stays up or down
+    /// all day
+    pub fn try_sunrise(self, dt: DateTime<Utc>) -> Result<DateTime<Utc>, SolarError> {
+        self.refined_event(dt, self.horizon_zenith(), true)
+    }
+
+    /// Returns sunset on the day of `dt`, or an error saying whether the sun stays up or down
+    /// all day
+    pub fn try_sunset(self, dt: DateTime<Utc>) -> Result<DateTime<Utc>, SolarError> {
+        self.refined_event(dt, self.horizon_zenith(), false)
+    }
+
+    /// Returns the sun's hour angle in degrees at the given instant: how far the earth has turned
+    /// since the sun crossed the meridian. It's 0 at solar noon, negative before it and positive
+    /// after, and runs from -180 to 180, one degree every four minutes on Earth.
+    pub fn hour_angle(self, dt: DateTime<Utc>) -> f64 {
+        self.hour_angle_at(self.planet.params(dt), dt)
+    }
+
+    /// Returns the true (apparent) solar time at the given instant, in minutes since solar
+    /// midnight, so 720 at solar noon on Earth. This is the time a sundial shows: the local mean
+    /// time for the longitude, corrected by the equation of time.
+    pub fn true_solar_time(self, dt: DateTime<Utc>) -> f64 {
+        self.hour_angle(dt) * self.planet.minutes_per_degree() + self.planet.day_minutes / 2.
+    }
+
+    /// Returns the sun's `(elevation, azimuth)` in degrees at the given instant. Elevation is
+    /// measured up from the horizon, and azimuth clockwise from north.
+    pub fn solar_position(self, dt: DateTime<Utc>) -> (f64, f64) {
+        let params = self.planet.params(dt);
+        let decl = params.decl;
+        let ha = self.hour_angle_at(params, dt).to_radians();
+        let lat = self.lat.to_radians();
+        let cos_zenith = lat.sin() * decl.sin() + lat.cos() * decl.cos() * ha.cos();
+        let elevation = 90. - cos_zenith.clamp(-1., 1.).acos().to_degrees();
+        let azimuth = ha
+            .sin()
+            .atan2(ha.cos() * lat.sin() - decl.tan() * lat.cos())
+            .to_degrees()
+            + 180.;
+        (elevation, azimuth.rem_euclid(360.))
+    }
+
+    /// Whether the sun is up at the given instant: its centre is above the 90.883° sunrise and
+    /// sunset zenith (plus the horizon's dip, for an observer above sea level). Consistent with
+    /// `sunrise` and `sunset`, and simply always true or false in polar day or night.
+    pub fn is_daytime(self, dt: DateTime<Utc>) -> bool {
+        self.solar_position(dt).0 > self.horizon_elevation()
+    }
+
+    /// Elevation of the sun's centre at sunrise and sunset, in degrees: -0.883° at sea level,
+    /// and lower from higher up, where the horizon dips below the horizontal
+    pub fn horizon_elevation(self) -> f64 {
+        90. - self.horizon_zenith()
+    }
+
+    /// Like `solar_position`, but with the elevation raised by atmospheric refraction to where
+    /// the sun appears to be. Refraction is about half a degree at the horizon, which is most of
+    /// the allowance in the 90.883° sunrise zenith, and negligible high in the sky.
+    pub fn apparent_solar_position(self, dt: DateTime<Utc>) -> (f64, f64) {
+        let (elevation, azimuth) = self.solar_position(dt);
+        (elevation + refraction(elevation), azimuth)
+    }
+
+    /// Returns the angle in degrees between the sun and the normal of a surface tilted
+    /// `panel_tilt` degrees up from horizontal and facing `panel_azimuth` degrees clockwise from
+    /// north, or `None` while the sun is below the horizon. An angle over 90° means the sun is
+    /// behind the surface.
+    pub fn incidence_angle(
+        self,
+        dt: DateTime<Utc>,
+        panel_tilt: f64,
+        panel_azimuth: f64,
+    ) -> Option<f64> {
+        let (elevation, azimuth) = self.solar_position(dt);
+        if elevation <= 0. {
+            return None;
+        }
+        let (elevation, tilt) = (elevation.to_radians(), panel_tilt.to_radians());
+        let bearing = (azimuth - panel_azimuth).to_radians();
+        let cos_incidence =
+            elevation.sin() * tilt.cos() + elevation.cos() * tilt.sin() * bearing.cos();
+        Some(cos_incidence.clamp(-1., 1.).acos().to_degrees())
+    }
+
+    /// Returns how long a shadow is compared to the height of the object casting it on level
+    /// ground, `1 / tan(elevation)`, or `None` while the sun is at or below the horizon. Near
+    /// sunrise and sunset the ratio is capped at `MAX_SHADOW_RATIO`.
+    pub fn shadow_ratio(self, dt: DateTime<Utc>) -> Option<f64> {
+        let (elevation, _) = self.solar_position(dt);
+        if elevation <= 0. {
+            return None;
+        }
+        Some((1. / elevation.to_radians().tan()).min(MAX_SHADOW_RATIO))
+    }
+
+    /// Returns whether the sun is above the horizon at the midnight that ends `dt`'s calendar day
+    /// in `tz`, i.e. "will it be light at midnight tonight?" This is only true in the midnight
+    /// sun season, but unlike checking whether the sun sets at all, it answers for the specific
+    /// moment of local midnight.
+    pub fn sun_up_at_local_midnight<Tz: TimeZone>(self, dt: DateTime<Utc>, tz: &Tz) -> bool {
+        let midnight = dt
+            .with_timezone(tz)
+            .naive_local()
+            .date()
+            .succ_opt()
+            .and_then(|date| date.and_hms_opt(0, 0, 0))
+            .expect("Dates this far out aren't supported by the solar model anyway");
+        // If midnight is skipped by a DST change, the clock jumps straight to 1am
+        let instant = tz
+            .from_local_datetime(&midnight)
+            .earliest()
+            .or_else(|| tz.from_local_datetime(&(midnight + Duration::hours(1))).earliest())
+            .expect("DST changes skip at most an hour");
+        self.solar_position(instant.with_timezone(&Utc)).0 > 0.
+    }
+
+    /// Returns the first time on the day of `dt` that the sun, while above the horizon, bears
+    /// `azimuth` degrees clockwise from north (so due south is 180°). Returns `None` if the sun
+    /// never reaches that bearing during daylight.
+    pub fn time_at_azimuth(self, dt: DateTime<Utc>, azimuth: f64) -> Option<DateTime<Utc>> {
+        // Signed difference between the sun's bearing and the target, in (-180, 180]
+        let offset = |t: DateTime<Utc>| {
+            let (elevation, az) = self.solar_position(t);
+            (elevation > 0., (az - azimuth + 180.).rem_euclid(360.) - 180.)
+        };
+        let step = Duration::minutes(10);
+        let mut t = self.solar_noon(dt) - Duration::hours(12);
+        let end = t + Duration::days(1);
+        let (mut up, mut diff) = offset(t);
+        while t < end {
+            let next = t + step;
+            let (next_up, next_diff) = offset(next);
+            // Ignore the jump where the difference wraps around behind the observer
+            let crosses = (diff <= 0.) != (next_diff <= 0.)
+                && diff.abs() < 90.
+                && next_diff.abs() < 90.;
+            if up && next_up && crosses {
+                let (mut lo, mut hi) = (t, next);
+                for _ in 0..20 {
+                    let mid = lo + (hi - lo) / 2;
+                    if (offset(mid).1 <= 0.) == (diff <= 0.) {
+                        lo = mid;
+                    } else {
+                        hi = mid;
+                    }
+                }
+                return Some(lo);
+            }
+            t = next;
+            up = next_up;
+            diff = next_diff;
+        }
+        None
+    }
+
+    /// Hour angle in degrees at the given instant: zero at solar noon, negative in the morning
+    fn hour_angle_at(self, params: SolarParams, dt: DateTime<Utc>) -> f64 {
+        let minutes = self.planet.minutes_into_day(dt);
+        let ha = (minutes - self.noon_minutes(params)) / self.planet.minutes_per_degree();
+        (ha + 180.).rem_euclid(360.) - 180.
+    }
+
+    /// Minutes after midnight UTC at which the sun crosses this meridian
+    fn noon_minutes(self, params: SolarParams) -> f64 {
+        raw::noon_minutes(self.planet.day_minutes, self.long, params.eqtime)
+    }
+
+    /// Zenith angle of the sun's centre at sunrise and sunset: the standard 90.883° (allowing for
+    /// refraction and the sun's radius) plus the dip of the horizon seen from the observer's
+    /// elevation. At sea level this is exactly the standard value.
+    fn horizon_zenith(self) -> f64 {
+        SUNRISE_ZENITH + 2.076 * self.elevation.max(0.).sqrt() / 60.
+    }
+
+    /// Cosine of the hour angle at sunrise. Values above 1 mean the sun never rises; values
+    /// below -1 mean it never sets.
+    fn cos_zenith_hour_angle(self, params: SolarParams) -> f64 {
+        self.cos_hour_angle_for_zenith(params, self.horizon_zenith())
+    }
+
+    /// Cosine of the hour angle at which the sun's centre is `zenith` degrees from straight up.
+    /// Values outside [-1, 1] mean it never gets that low (above 1) or high (below -1).
+    fn cos_hour_angle_for_zenith(self, params: SolarParams, zenith: f64) -> f64 {
+        raw::cos_zenith_hour_angle(self.lat.to_radians(), params.decl, zenith)
+    }
+
+    /// Returns when the centre of the sun is at `elevation` degrees above the horizon (negative
+    /// for below) on the day of `dt`, in the morning if `rising` and the evening otherwise. This
+    /// is the geometric elevation, with no allowance for refraction. Returns `None` if the sun
+    /// never passes through that elevation that day.
+    pub fn time_at_elevation(
+        self,
+        dt: DateTime<Utc>,
+        elevation: f64,
+        rising: bool,
+    ) -> Option<DateTime<Utc>> {
+        self.refined_event(dt, 90. - elevation, rising).ok()
+    }
+
+    /// Start of civil twilight, when the sun rises to 6° below the horizon. `None` if it doesn't
+    /// get that low (or high) on the day of `dt`, as is common at high latitudes.
+    pub fn civil_dawn(self, dt: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.refined_event(dt, CIVIL_ZENITH, true).ok()
+    }
+
+    /// End of civil twilight, when the sun sets to 6° below the horizon
+    pub fn civil_dusk(self, dt: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.refined_event(dt, CIVIL_ZENITH, false).ok()
+    }
+
+    /// Start of nautical twilight, when the sun rises to 12° below the horizon
+    pub fn nautical_dawn(self, dt: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.refined_event(dt, NAUTICAL_ZENITH, true).ok()
+    }
+
+    /// End of nautical twilight, when the sun sets to 12° below the horizon
+    pub fn nautical_dusk(self, dt: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.refined_event(dt, NAUTICAL_ZENITH, false).ok()
+    }
+
+    /// Start of astronomical twilight, when the sun rises to 18° below the horizon
+    pub fn astronomical_dawn(self, dt: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.refined_event(dt, ASTRONOMICAL_ZENITH, true).ok()
+    }
+
+    /// End of astronomical twilight, when the sun sets to 18° below the horizon
+    pub fn astronomical_dusk(self, dt: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.refined_event(dt, ASTRONOMICAL_ZENITH, false).ok()
+    }
+
+    /// Returns the morning and evening periods on the day of `dt` that the sun's centre is
+    /// between `low` and `high` degrees of elevation. A period is `None` if the sun doesn't pass
+    /// all the way through the band, such as when it never sets below `low` or rises above
+    /// `high`.
+    fn elevation_band(self, dt: DateTime<Utc>, low: f64, high: f64) -> DailyWindows {
+        let morning = self
+            .time_at_elevation(dt, low, true)
+            .zip(self.time_at_elevation(dt, high, true))
+            .map(|(start, end)| start..end);
+        let evening = self
+            .time_at_elevation(dt, high, false)
+            .zip(self.time_at_elevation(dt, low, false))
+            .map(|(start, end)| start..end);
+        (morning, evening)
+    }
+
+    /// Returns the morning and evening golden hours on the day of `dt`, when the sun is between
+    /// 4° below and 6° above the horizon. Either is `None` if the sun doesn't pass all the way
+    /// through that band.
+    pub fn golden_hour(self, dt: DateTime<Utc>) -> DailyWindows {
+        self.elevation_band(dt, -4., 6.)
+    }
+
+    /// Returns the morning and evening blue hours on the day of `dt`, when the sun is between 6°
+    /// and 4° below the horizon. Either is `None` if the sun doesn't pass all the way through
+    /// that band.
+    pub fn blue_hour(self, dt: DateTime<Utc>) -> DailyWindows {
+        self.elevation_band(dt, -6., -4.)
+    }
+
+    /// Returns dawn and dusk at sunrise/sunset and at each of the civil (6° below the horizon),
+    /// nautical (12°) and astronomical (18°) twilight depressions, all in one pass. Unlike the
+    /// individual event methods, which refine each event separately, this computes the day's
+    /// declination and equation of time once at solar noon and shares them between all eight
+    /// events; results agree with the separate calculations to within a minute or so.
+    pub fn all_twilights(self, dt: DateTime<Utc>) -> TwilightSet {
+        let params = self.planet.params(self.solar_noon(dt));
+        let day_start = self.planet.day_start(dt);
+        let twilight = |zenith| Twilight {
+            dawn: self.event_with_params(day_start, params, zenith, true).ok(),
+            dusk: self.event_with_params(day_start, params, zenith, false).ok(),
+        };
+        TwilightSet {
+            official: twilight(self.horizon_zenith()),
+            civil: twilight(CIVIL_ZENITH),
+            nautical: twilight(NAUTICAL_ZENITH),
+            astronomical: twilight(ASTRONOMICAL_ZENITH),
+        }
+    }
+
+    /// Minutes of daylight on the day of `dt`, which are zero during polar night and the whole
+    /// day during polar day.
+    fn daylight_minutes(self, dt: DateTime<Utc>) -> f64 {
+        let cos_ha = self.cos_zenith_hour_angle(self.planet.params(dt)).clamp(-1., 1.);
+        2. * raw::event_offset(self.planet.day_minutes, cos_ha)
+    }
+
+    /// Lists the reasons, if any, that results for this position in the given year may be less
+    /// accurate than usual. High latitudes start to degrade past ±60°, and the fitted series are
+    /// only trusted within a couple of centuries of the present.
+    pub fn accuracy_warnings(self, year: i32) -> Vec<AccuracyWarning> {
+        let mut warnings = vec![];
+        if self.lat.abs() > 60. {
+            warnings.push(AccuracyWarning::HighLatitude(self.lat));
+        }
+        if !(1900..=2100).contains(&year) {
+            warnings.push(AccuracyWarning::DistantYear(year));
+        }
+        warnings
+    }
+
+    /// Total hours the sun is up over the given calendar year. Away from the poles this stays
+    /// close to half the year (about 4380h, plus a little extra from atmospheric refraction).
+    pub fn annual_daylight_hours(self, year: i32) -> f64 {
+        days_of_year(year)
+            .map(|date| self.daylight_minutes(noon_utc(date)))
+            .sum::<f64>()
+            / 60.
+    }
+
+    /// Returns sunrise, solar noon and sunset for the day of `dt`, exactly as `sunrise`,
+    /// `solar_noon` and `sunset` give them. Polar days and nights are treated as in `sunrise`
+    /// and `sunset`.
+    pub fn events(self, dt: DateTime<Utc>) -> SolarEvents {
+        SolarEvents {
+            sunrise: self.sunrise(dt),
+            solar_noon: self.solar_noon(dt),
+            sunset: self.sunset(dt),
+        }
+    }
+
+    /// Lists every day of `year` whose events satisfy `predicate`, such as "sunrise before 5am"
+    /// or "longer than 15 hours". This computes the events for each of the year's days in turn.
+    pub fn days_where(self, year: i32, predicate: impl Fn(&SolarEvents) -> bool) -> Vec<NaiveDate> {
+        days_of_year(year)
+            .filter(|date| predicate(&self.events(noon_utc(*date))))
+            .collect()
+    }
+
+    /// Yields each day of `range` with its events, computed (at noon UTC) only as each day is
+    /// reached, so even very long ranges cost nothing up front
+    pub fn events_over(
+        self,
+        range: RangeInclusive<NaiveDate>,
+    ) -> impl Iterator<Item = (NaiveDate, SolarEvents)> {
+        days(range).map(move |date| (date, self.events(noon_utc(date))))
+    }
+}
+
+/// Morning and evening crossings of one solar depression. Either is `None` if the sun doesn't
+/// cross that depression on the day.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Twilight {
+    pub dawn: Option<DateTime<Utc>>,
+    pub dusk: Option<DateTime<Utc>>,
+}
+
+/// Every standard twilight for one day, as returned by [`Pos::all_twilights`]. `official` is
+/// sunrise and sunset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TwilightSet {
+    pub official: Twilight,
+    pub civil: Twilight,
+    pub nautical: Twilight,
+    pub astronomical: Twilight,
+}
+
+#[test]
+fn test_solar_noon() {
+    // NOAA's solar calculator puts noon at Greenwich at 12:14:13 and 11:43:36 UTC on these
+    // dates, close to the extremes of the equation of time
+    let greenwich = Pos::new(51.48, 0.);
+    let cases = [((2023, 2, 11), (12, 14, 13)), ((2023, 11, 3), (11, 43, 36))];
+    for ((y, mo, d), (h, mi, s)) in cases {
+        let expected = Utc.with_ymd_and_hms(y, mo, d, h, mi, s).unwrap();
+        for hour in [0, 12, 23] {
+            let noon = greenwich.solar_noon(Utc.with_ymd_and_hms(y, mo, d, hour, 0, 0).unwrap());
+            assert!((noon - expected).num_seconds().abs() <= 15, "{noon} vs {expected}");
+        }
+    }
+
+    // Two passes have converged: refining again doesn't change the result
+    let washington = Pos::new(38.9, -77.04);
+    let dt = Utc.with_ymd_and_hms(2023, 6, 21, 0, 0, 0).unwrap();
+    let noon = washington.solar_noon(dt);
+    assert_eq!(washington.refine_noon(Planet::EARTH.day_start(dt), noon), noon);
+}
+
+#[test]
+fn test_mars_noon() {
+    // Each sol's noon comes one sol after the last, drifting about 40 minutes later each Earth day
+    let mars = Pos::new(4.5, 137.4).with_planet(Planet::MARS);
+    let sol = Duration::milliseconds((Planet::MARS.day_minutes * 60_000.) as i64);
+    let mut noon = mars.solar_noon(Utc.with_ymd_and_hms(2023, 3, 20, 12, 0, 0).unwrap());
+    for _ in 0..10 {
+        let next = mars.solar_noon(noon + sol);
+        assert!(((next - noon) - sol).num_seconds().abs() <= 1, "{noon} then {next}");
+        assert!(mars.hour_angle(next).abs() < 0.01, "{next}");
+        noon = next;
+    }
+    // Sunrise and sunset fall either side of noon on the same sol
+    let (sunrise, sunset) = (mars.sunrise(noon), mars.sunset(noon));
+    assert!(sunrise < noon && noon < sunset, "{sunrise} {noon} {sunset}");
+    assert!(((noon - sunrise) - (sunset - noon)).num_seconds().abs() <= 60);
+}
+
+#[test]
+fn test_mars_sunrise_at_gale() {
+    // Sunrise and sunset at Gale crater from Mars24's algorithm (Allison & McEwen 2000), on the
+    // sol Curiosity landed and one in northern winter. The circular model leaves out Mars's
+    // equation of time, which reaches about 50 minutes, but gets the sol and season right.
+    let gale = Pos::new(-4.6, 137.4).with_planet(Planet::MARS);
+    let at = |y, m, d, h, min| Utc.with_ymd_and_hms(y, m, d, h, min, 0).unwrap();
+    let cases = [
+        (at(2012, 8, 6, 12, 0), at(2012, 8, 6, 20, 9), at(2012, 8, 7, 8, 21)),
+        (at(2024, 1, 1, 0, 0), at(2024, 1, 1, 6, 8), at(2024, 1, 1, 18, 26)),
+    ];
+    for (after, sunrise, sunset) in cases {
+        let found = gale.next_sunrise(after).unwrap();
+        assert!((found - sunrise).num_minutes().abs() < 50, "{found} vs {sunrise}");
+        let found = gale.next_sunset(after).unwrap();
+        assert!((found - sunset).num_minutes().abs() < 50, "{found} vs {sunset}");
+    }
+}
+
+#[test]
+fn test_events_across_utc_midnight() {
+    let date = |y, m, d| NaiveDate::from_ymd_opt(y, m, d).unwrap();
+    let dt = Utc.with_ymd_and_hms(2023, 6, 21, 12, 0, 0).unwrap();
+    // Just west of the date line, morning comes on the previous UTC day
+    let east = Pos::new(0., 179.);
+    assert_eq!(east.sunrise(dt).naive_utc().date(), date(2023, 6, 20));
+    assert_eq!(east.sunset(dt).naive_utc().date(), date(2023, 6, 21));
+    // Just east of it, evening comes on the next one
+    let west = Pos::new(0., -179.);
+    assert_eq!(west.sunrise(dt).naive_utc().date(), date(2023, 6, 21));
+    assert_eq!(west.sunset(dt).naive_utc().date(), date(2023, 6, 22));
+
+    // Right on the date line, the equation of time pushes noon itself either side of midnight
+    let midnight = |m, d| Utc.with_ymd_and_hms(2023, m, d, 0, 0, 0).unwrap();
+    let noon = Pos::new(0., 179.9).solar_noon(midnight(11, 3));
+    assert_eq!(noon.naive_utc().date(), date(2023, 11, 2));
+    let noon = Pos::new(0., -179.9).solar_noon(midnight(2, 11));
+    assert_eq!(noon.naive_utc().date(), date(2023, 2, 12));
+}
+
+#[test]
+fn test_southern_hemisphere() {
+    // Sunrise and sunset in UTC from NOAA's solar calculator, at the solstices and equinoxes
+    let cities = [
+        ("Sydney", Pos::new(-33.87, 151.21)),
+        ("Cape Town", Pos::new(-33.92, 18.42)),
+        ("Buenos Aires", Pos::new(-34.6, -58.38)),
+    ];
+    let expected = [
+        [
+            [(12, 20, 18, 40), (12, 21, 9, 5)],
+            [(6, 20, 20, 59), (6, 21, 6, 53)],
+            [(3, 19, 19, 58), (3, 20, 8, 8)],
+            [(9, 22, 19, 44), (9, 23, 7, 50)],
+        ],
+        [
+            [(12, 21, 3, 31), (12, 21, 17, 56)],
+            [(6, 21, 5, 50), (6, 21, 15, 44)],
+            [(3, 20, 4, 49), (3, 20, 16, 59)],
+            [(9, 23, 4, 35), (9, 23, 16, 42)],
+        ],
+        [
+            [(12, 21, 8, 37), (12, 21, 23, 5)],
+            [(6, 21, 10, 59), (6, 21, 20, 49)],
+            [(3, 20, 9, 56), (3, 20, 22, 7)],
+            [(9, 23, 9, 42), (9, 23, 21, 49)],
+        ],
+    ];
+    let at = |(m, d, h, min)| Utc.with_ymd_and_hms(2023, m, d, h, min, 0).unwrap();
+    let days = [(12, 21), (6, 21), (3, 20), (9, 23)];
+    for ((name, pos), expected) in cities.into_iter().zip(expected) {
+        for ((m, d), [sunrise, sunset]) in days.into_iter().zip(expected) {
+            let dt = at((m, d, 12, 0));
+            let (sunrise, sunset) = (at(sunrise), at(sunset));
+            assert!((pos.sunrise(dt) - sunrise).num_minutes().abs() <= 2, "{name} {sunrise}");
+            assert!((pos.sunset(dt) - sunset).num_minutes().abs() <= 2, "{name} {sunset}");
+            assert!(pos.sunrise(dt) < pos.solar_noon(dt) && pos.solar_noon(dt) < pos.sunset(dt));
+        }
+        // The long days come in December and the short ones in June
+        let (december, june) = (at((12, 21, 12, 0)), at((6, 21, 12, 0)));
+        assert!(pos.day_length(december) > Duration::hours(14), "{name}");
+        assert!(pos.day_length(june) < Duration::hours(10), "{name}");
+    }
+
+    // Mirroring the latitude mirrors the seasons
+    for lat in [10., 35., 60.] {
+        let dt = |m, d| Utc.with_ymd_and_hms(2023, m, d, 12, 0, 0).unwrap();
+        let north = Pos::new(lat, 0.).day_length(dt(6, 21));
+        let south = Pos::new(-lat, 0.).day_length(dt(12, 21));
+        assert!((north - south).num_minutes().abs() <= 5, "{lat}: {north} vs {south}");
+    }
+
+    // Midnight sun in December and polar night in June at McMurdo Station
+    let mcmurdo = Pos::new(-77.85, 166.67);
+    let december = Utc.with_ymd_and_hms(2023, 12, 21, 12, 0, 0).unwrap();
+    let june = Utc.with_ymd_and_hms(2023, 6, 21, 12, 0, 0).unwrap();
+    assert_eq!(mcmurdo.try_sunrise(december), Err(SolarError::SunAlwaysUp));
+    assert_eq!(mcmurdo.try_sunset(june), Err(SolarError::SunAlwaysDown));
+}
+
+#[test]
+fn test_hour_angle() {
+    let pos = Pos::new(40., -75.);
+    let noon = pos.solar_noon(Utc.with_ymd_and_hms(2023, 11, 3, 12, 0, 0).unwrap());
+    assert!(pos.hour_angle(noon).abs() < 0.01, "{}", pos.hour_angle(noon));
+    assert!((pos.true_solar_time(noon) - 720.).abs() < 0.05);
+    let afternoon = noon + Duration::hours(3);
+    assert!((pos.hour_angle(afternoon) - 45.).abs() < 0.02);
+    assert!((pos.true_solar_time(afternoon) - 900.).abs() < 0.05);
+    let morning = noon - Duration::hours(6);
+    assert!((pos.hour_angle(morning) + 90.).abs() < 0.02);
+    // True solar time is local mean time plus the equation of time, which is over 16 minutes
+    // ahead in early November
+    let mean_time = noon.num_seconds_from_midnight() as f64 / 60. - 75. * 4.;
+    assert!((pos.true_solar_time(noon) - mean_time - 16.4).abs() < 0.5);
+}
+
+#[test]
+fn test_is_daytime() {
+    let pos = Pos::new(51.5, 0.);
+    let dt = Utc.with_ymd_and_hms(2023, 3, 20, 12, 0, 0).unwrap();
+    assert!(pos.is_daytime(dt));
+    assert!(!pos.is_daytime(dt + Duration::hours(12)));
+    let (sunrise, sunset) = (pos.sunrise(dt), pos.sunset(dt));
+    assert!(!pos.is_daytime(sunrise - Duration::minutes(1)));
+    assert!(pos.is_daytime(sunrise + Duration::minutes(1)));
+    assert!(pos.is_daytime(sunset - Duration::minutes(1)));
+    assert!(!pos.is_daytime(sunset + Duration::minutes(1)));
+    assert_eq!(pos.horizon_elevation(), 90. - 90.883);
+    assert!(Pos::with_elevation(51.5, 0., 1000.).horizon_elevation() < -1.5);
+
+    // Midnight sun and polar night in Tromsø
+    let tromso = Pos::new(69.6, 19.);
+    let midsummer = Utc.with_ymd_and_hms(2023, 6, 21, 0, 0, 0).unwrap();
+    let midwinter = Utc.with_ymd_and_hms(2023, 12, 21, 0, 0, 0).unwrap();
+    for hour in 0..24 {
+        assert!(tromso.is_daytime(midsummer + Duration::hours(hour)));
+        assert!(!tromso.is_daytime(midwinter + Duration::hours(hour)));
+    }
+}
+
+#[test]
+fn test_incidence_angle() {
+    let pos = Pos::new(40., -75.);
+    let noon = pos.solar_noon(Utc.with_ymd_and_hms(2023, 3, 20, 17, 0, 0).unwrap());
+    // At the equinox, a south-facing panel tilted at the latitude faces the noon sun head on
+    let angle = pos.incidence_angle(noon, 40., 180.).unwrap();
+    assert!(angle < 1., "{angle}");
+    // A flat panel's incidence is the sun's zenith angle
+    let flat = pos.incidence_angle(noon, 0., 180.).unwrap();
+    assert!((flat - (90. - pos.solar_position(noon).0)).abs() < 1e-9);
+    // No incidence at night
+    assert_eq!(pos.incidence_angle(noon + Duration::hours(12), 40., 180.), None);
+}
+
+#[test]
+fn test_shadow_ratio() {
+    let pos = Pos::new(45., 0.);
+    let noon = pos.solar_noon(Utc.with_ymd_and_hms(2023, 3, 20, 12, 0, 0).unwrap());
+    // At the equinox the noon sun is about 45° up at 45°N, so shadows are as long as the object
+    let ratio = pos.shadow_ratio(noon).unwrap();
+    assert!((ratio - 1.).abs() < 0.02, "{ratio}");
+    let elevation = pos.solar_position(noon).0.to_radians();
+    assert!((ratio * elevation.tan() - 1.).abs() < 1e-9);
+    // Just after sunrise the ratio is capped, and there's no shadow at night
+    let dawn = pos.time_at_elevation(noon, 0.1, true).unwrap();
+    assert_eq!(pos.shadow_ratio(dawn), Some(MAX_SHADOW_RATIO));
+    assert_eq!(pos.shadow_ratio(noon + Duration::hours(12)), None);
+}
+
+#[test]
+fn test_meridian_sunrise_sunset() {
+    let dt = Utc.with_ymd_and_hms(2023, 6, 21, 12, 0, 0).unwrap();
+    let events = meridian_sunrise_sunset(20., dt, &[-80., 0., 45., 80.]);
+    assert_eq!(events.len(), 4);
+    // Polar night in the south and midnight sun in the north
+    assert_eq!(events[0], Twilight { dawn: None, dusk: None });
+    assert_eq!(events[3], Twilight { dawn: None, dusk: None });
+    for (lat, event) in [(0., &events[1]), (45., &events[2])] {
+        let pos = Pos::new(lat, 20.);
+        assert!((event.dawn.unwrap() - pos.sunrise(dt)).num_seconds().abs() < 60);
+        assert!((event.dusk.unwrap() - pos.sunset(dt)).num_seconds().abs() < 60);
+    }
+    // Days are longer further north in June
+    let length = |event: &Twilight| event.dusk.unwrap() - event.dawn.unwrap();
+    assert!(length(&events[2]) > length(&events[1]));
+}
+
+#[test]
+fn test_twilight_methods() {
+    let pos = Pos::new(40., -75.);
+    let dt = Utc.with_ymd_and_hms(2023, 3, 20, 17, 0, 0).unwrap();
+    let astronomical = pos.astronomical_dawn(dt).unwrap();
+    let nautical = pos.nautical_dawn(dt).unwrap();
+    let civil = pos.civil_dawn(dt).unwrap();
+    assert!(astronomical < nautical && nautical < civil && civil < pos.sunrise(dt));
+    let civil = pos.civil_dusk(dt).unwrap();
+    let nautical = pos.nautical_dusk(dt).unwrap();
+    let astronomical = pos.astronomical_dusk(dt).unwrap();
+    assert!(pos.sunset(dt) < civil && civil < nautical && nautical < astronomical);
+    // Around 25 minutes of civil twilight at the equinox at this latitude
+    let length = pos.civil_dawn(dt).map(|dawn| pos.sunrise(dt) - dawn).unwrap();
+    assert!((20..35).contains(&length.num_minutes()), "{length}");
+
+    // London's summer nights never get astronomically dark, but are still civil-dark
+    let june = Utc.with_ymd_and_hms(2023, 6, 21, 12, 0, 0).unwrap();
+    let london = Pos::new(51.5, 0.);
+    assert_eq!(london.astronomical_dawn(june), None);
+    assert_eq!(london.astronomical_dusk(june), None);
+    assert!(london.civil_dawn(june).is_some());
+}
+
+#[test]
+fn test_event_for_zenith() {
+    let pos = Pos::new(40., -75.);
+    let dt = Utc.with_ymd_and_hms(2023, 3, 20, 17, 0, 0).unwrap();
+    assert_eq!(pos.event_for_zenith(dt, 90.883), (pos.sunrise(dt), pos.sunset(dt)));
+    // The sun is 6° up some time after sunrise and before sunset
+    let (morning, evening) = pos.event_for_zenith(dt, 84.);
+    assert!(morning > pos.sunrise(dt) && evening < pos.sunset(dt));
+    let elevation_time = pos.time_at_elevation(dt, 6., true).unwrap();
+    assert!((morning - elevation_time).num_seconds().abs() < 60);
+}
+
+#[test]
+fn test_time_at_elevation() {
+    let pos = Pos::new(40., -75.);
+    let dt = Utc.with_ymd_and_hms(2023, 6, 1, 17, 0, 0).unwrap();
+    let noon = pos.solar_noon(dt);
+    // 30° up this morning and this afternoon, either side of noon
+    let morning = pos.time_at_elevation(dt, 30., true).unwrap();
+    let afternoon = pos.time_at_elevation(dt, 30., false).unwrap();
+    assert!(morning < noon && noon < afternoon);
+    for time in [morning, afternoon] {
+        let elevation = pos.solar_position(time).0;
+        assert!((elevation - 30.).abs() < 0.05, "{time} {elevation}");
+    }
+    // The June sun peaks around 73° here, and never sinks 30° below the horizon
+    assert_eq!(pos.time_at_elevation(dt, 80., true), None);
+    assert_eq!(pos.time_at_elevation(dt, -30., false), None);
+}
+
+#[test]
+fn test_decl_against_reference() {
+    // Declination at 12:00 UTC from the NOAA solar calculator. The fitted series is good to
+    // well under a tenth of a degree at the solstices but drifts by a few tenths around the
+    // equinoxes, when declination is changing fastest.
+    let cases = [
+        ((3, 20), -0.15, 0.5),
+        ((6, 21), 23.44, 0.1),
+        ((9, 23), -0.11, 0.5),
+        ((12, 22), -23.44, 0.1),
+    ];
+    for ((month, day), expected, tolerance) in cases {
+        let dt = Utc.with_ymd_and_hms(2023, month, day, 12, 0, 0).unwrap();
+        let actual = decl(gamma(dt)).to_degrees();
+        assert!((actual - expected).abs() < tolerance, "{month}-{day}: {actual}");
+    }
+}
+
+#[test]
+fn test_try_sunrise_polar() {
+    let june = Utc.with_ymd_and_hms(2023, 6, 21, 12, 0, 0).unwrap();
+    let north = Pos::new(80., 20.);
+    let south = Pos::new(-80., 20.);
+    assert_eq!(north.try_sunrise(june), Err(SolarError::SunAlwaysUp));
+    assert_eq!(north.try_sunset(june), Err(SolarError::SunAlwaysUp));
+    assert_eq!(south.try_sunrise(june), Err(SolarError::SunAlwaysDown));
+    assert_eq!(south.try_sunset(june), Err(SolarError::SunAlwaysDown));
+    assert_eq!(north.sunset(june) - north.sunrise(june), Duration::days(1));
+    assert_eq!(south.sunset(june) - south.sunrise(june), Duration::zero());
+
+    let london = Pos::new(51.5, 0.);
+    assert_eq!(london.try_sunrise(june), Ok(london.sunrise(june)));
+    assert_eq!(london.try_sunset(june), Ok(london.sunset(june)));
+}
+
+#[test]
+fn test_day_length() {
+    let dt = Utc.with_ymd_and_hms(2023, 3, 20, 12, 0, 0).unwrap();
+    // A little over 12 hours, thanks to refraction and the sun's radius
+    let length = Pos::new(0., 0.).day_length(dt);
+    assert!((720..735).contains(&length.num_minutes()), "{length}");
+
+    let june = Utc.with_ymd_and_hms(2023, 6, 21, 12, 0, 0).unwrap();
+    assert_eq!(Pos::new(80., 0.).day_length(june), Duration::days(1));
+    assert_eq!(Pos::new(-80., 0.).day_length(june), Duration::zero());
+}
+
+#[test]
+fn test_apparent_solar_position() {
+    let pos = Pos::new(40., -75.);
+    let dt = Utc.with_ymd_and_hms(2023, 3, 20, 12, 0, 0).unwrap();
+    // Refraction lifts the sun about half a degree at the horizon
+    let sunrise = pos.sunrise(dt);
+    let (geometric, azimuth) = pos.solar_position(sunrise);
+    let (apparent, apparent_azimuth) = pos.apparent_solar_position(sunrise);
+    assert_eq!(azimuth, apparent_azimuth);
+    assert!((0.3..0.7).contains(&(apparent - geometric)), "{geometric} {apparent}");
+    // ... and by well under a minute of arc at noon
+    let noon = pos.solar_noon(dt);
+    let (geometric, azimuth) = pos.solar_position(noon);
+    assert!((azimuth - 180.).abs() < 1., "{azimuth}");
+    assert!((pos.apparent_solar_position(noon).0 - geometric).abs() < 1. / 60.);
+}
+
+#[test]
+fn test_golden_and_blue_hour() {
+    let pos = Pos::new(40., -75.);
+    let dt = Utc.with_ymd_and_hms(2023, 3, 20, 17, 0, 0).unwrap();
+    let (golden_morning, golden_evening) = pos.golden_hour(dt);
+    let (blue_morning, blue_evening) = pos.blue_hour(dt);
+    let (golden_morning, golden_evening) = (golden_morning.unwrap(), golden_evening.unwrap());
+    let (blue_morning, blue_evening) = (blue_morning.unwrap(), blue_evening.unwrap());
+    // Blue hour runs straight into golden hour in the morning, and the reverse in the evening
+    assert!((golden_morning.start - blue_morning.end).num_seconds().abs() < 60);
+    assert!((blue_evening.start - golden_evening.end).num_seconds().abs() < 60);
+    assert!(golden_morning.contains(&pos.sunrise(dt)));
+    assert!(golden_evening.contains(&pos.sunset(dt)));
+    assert!(golden_morning.end < golden_evening.start);
+
+    // At midwinter in the far north the sun never gets 6° up, so there's no complete window
+    let december = Utc.with_ymd_and_hms(2023, 12, 21, 12, 0, 0).unwrap();
+    let (morning, evening) = Pos::new(65., 25.).golden_hour(december);
+    assert_eq!((morning, evening), (None, None));
+}
+
+#[test]
+fn test_pos_serde() {
+    let pos: Pos = serde_json::from_str(r#"{"lat": 51.5, "long": -0.13}"#).unwrap();
+    assert_eq!((pos.lat(), pos.long()), (51.5, -0.13));
+    assert_eq!(pos.planet, Planet::EARTH);
+    assert_eq!(pos.elevation, 0.);
+
+    let mars = Pos::with_elevation(4.5, 137.4, 10.).with_planet(Planet::MARS);
+    let round_trip: Pos = serde_json::from_str(&serde_json::to_string(&mars).unwrap()).unwrap();
+    assert_eq!((round_trip.lat, round_trip.long), (mars.lat, mars.long));
+    assert_eq!((round_trip.planet, round_trip.elevation), (Planet::MARS, 10.));
+
+    // Coordinates are checked as `try_new` checks them
+    let wrapped: Pos = serde_json::from_str(r#"{"lat": 45, "long": 400}"#).unwrap();
+    assert_eq!((wrapped.lat(), wrapped.long()), (45., 40.));
+    assert!(serde_json::from_str::<Pos>(r#"{"lat": 95, "long": 400}"#).is_err());
+    assert!(serde_json::from_str::<Pos>(r#"{"lat": -122, "long": 45}"#).is_err());
+}
+
+#[test]
+fn test_orbit_model() {
+    // A slightly different Earth still gets the equation of time, as long as it says it's Earth
+    let earthlike = Planet { obliquity: 23.4393, ..Planet::EARTH };
+    let dt = Utc.with_ymd_and_hms(2023, 2, 11, 12, 0, 0).unwrap();
+    assert_eq!(earthlike.params(dt).eqtime, Planet::EARTH.params(dt).eqtime);
+    assert!(earthlike.params(dt).eqtime < -14.);
+    let json = serde_json::to_string(&earthlike).unwrap();
+    assert_eq!(serde_json::from_str::<Planet>(&json).unwrap().model, OrbitModel::Earth);
+
+    // Without a model, even Earth's numbers get a circular orbit
+    let json = r#"{"day_minutes": 1440, "year_days": 365, "obliquity": 23.44}"#;
+    let planet: Planet = serde_json::from_str(json).unwrap();
+    assert_eq!(planet.model, OrbitModel::Circular);
+    assert_eq!(planet.params(dt).eqtime, 0.);
+}
+
+#[test]
+fn test_try_new() {
+    assert!(Pos::try_new(45., -122.).is_ok());
+    assert!(Pos::try_new(-90., 180.).is_ok());
+    assert_eq!(Pos::try_new(-122., 45.).unwrap_err(), PosError::OutOfRange(-122., 45.));
+    assert_eq!(Pos::try_new(45., 180.5).unwrap().long(), -179.5);
+    assert!(Pos::try_new(f64::NAN, 0.).is_err());
+    assert!(Pos::try_new(0., f64::INFINITY).is_err());
+}
+
+#[test]
+fn test_normalized_longitude() {
+    let wrapped = Pos::new(0., 185.);
+    let west = Pos::new(0., -175.);
+    assert_eq!(wrapped.long(), -175.);
+    let dt = Utc.with_ymd_and_hms(2023, 3, 20, 12, 0, 0).unwrap();
+    assert_eq!(wrapped.sunrise(dt), west.sunrise(dt));
+    assert_eq!(wrapped.sunset(dt), west.sunset(dt));
+    assert_eq!(wrapped.solar_noon(dt), west.solar_noon(dt));
+    assert_eq!(wrapped.solar_position(dt), west.solar_position(dt));
+    // Whole turns either way come back to the same place, and the antimeridian is 180°
+    assert_eq!(Pos::new(0., -180.).long(), 180.);
+    assert_eq!(Pos::new(0., 180.).long(), 180.);
+    assert_eq!(Pos::new(0., -540.).long(), 180.);
+    assert_eq!(Pos::new(0., 540.).long(), 180.);
+    assert_eq!(Pos::new(0., 725.).long(), 5.);
+    assert_eq!(Pos::new(0., -0.5).long(), -0.5);
+    // Latitudes past the poles are clamped to them
+    assert_eq!(Pos::new(95., 0.).lat(), 90.);
+    assert_eq!(Pos::new(-91., 0.).lat(), -90.);
+    // Deserialized positions are normalized the same way
+    let wrapped: Pos = serde_json::from_str(r#"{"lat": 0, "long": 185}"#).unwrap();
+    assert_eq!(wrapped.long(), -175.);
+    let antimeridian: Pos = serde_json::from_str(r#"{"lat": 0, "long": -180}"#).unwrap();
+    assert_eq!(antimeridian.long(), 180.);
+}
+
+#[test]
+fn test_distance_and_bearing() {
+    let london = Pos::new(51.5074, -0.1278);
+    let paris = Pos::new(48.8566, 2.3522);
+    let new_york = Pos::new(40.7128, -74.0060);
+    let los_angeles = Pos::new(34.0522, -118.2437);
+    let close = |actual: f64, expected: f64, tolerance: f64| {
+        assert!((actual - expected).abs() < tolerance, "{actual} vs {expected}")
+    };
+    close(london.distance_km(paris), 343.5, 1.);
+    close(paris.distance_km(london), 343.5, 1.);
+    close(new_york.distance_km(los_angeles), 3936., 5.);
+    close(london.distance_km(new_york), 5570., 5.);
+    assert_eq!(london.distance_km(london), 0.);
+    // Halfway round the world
+    close(Pos::new(0., 0.).distance_km(Pos::new(0., 180.)), 20_015., 1.);
+
+    close(london.bearing_deg(paris), 148.1, 0.5);
+    close(paris.bearing_deg(london), 330.0, 0.5);
+    close(Pos::new(0., 0.).bearing_deg(Pos::new(0., 10.)), 90., 1e-9);
+    close(Pos::new(0., 0.).bearing_deg(Pos::new(0., -10.)), 270., 1e-9);
+    close(Pos::new(10., 20.).bearing_deg(Pos::new(40., 20.)), 0., 1e-9);
+    close(Pos::new(40., 20.).bearing_deg(Pos::new(10., 20.)), 180., 1e-9);
+}
+
+#[test]
+fn test_equation_of_time_and_declination() {
+    let at = |month, day| Utc.with_ymd_and_hms(2023, month, day, 12, 0, 0).unwrap();
+    // The equation of time's extremes: about -14¼ minutes in mid February and +16½ in early
+    // November
+    let february = equation_of_time(at(2, 11)).num_seconds() as f64 / 60.;
+    let november = equation_of_time(at(11, 3)).num_seconds() as f64 / 60.;
+    assert!((february + 14.2).abs() < 0.5, "{february}");
+    assert!((november - 16.4).abs() < 0.5, "{november}");
+    assert!((solar_declination(at(6, 21)) - 23.44).abs() < 0.1);
+    assert!((solar_declination(at(12, 22)) + 23.44).abs() < 0.1);
+}
+
+#[test]
+fn test_observer_elevation() {
+    let dt = Utc.with_ymd_and_hms(2023, 3, 20, 12, 0, 0).unwrap();
+    let sea_level = Pos::new(46., 7.);
+    assert_eq!(Pos::with_elevation(46., 7., 0.).sunrise(dt), sea_level.sunrise(dt));
+    // The horizon dips about 1.5° from 2000m, worth several minutes either end of the day
+    let mountain = Pos::with_elevation(46., 7., 2000.);
+    let earlier = sea_level.sunrise(dt) - mountain.sunrise(dt);
+    let later = mountain.sunset(dt) - sea_level.sunset(dt);
+    assert!((5..15).contains(&earlier.num_minutes()), "{earlier}");
+    assert!((5..15).contains(&later.num_minutes()), "{later}");
+}
+
+#[test]
+fn test_next_sunrise_sunset() {
+    let pos = Pos::new(40., -75.);
+    let day = Utc.with_ymd_and_hms(2023, 3, 20, 17, 0, 0).unwrap();
+    let next_day = day + Duration::days(1);
+    let (sunrise, sunset) = (pos.sunrise(day), pos.sunset(day));
+    assert_eq!(pos.next_sunrise(sunrise - Duration::minutes(1)), Some(sunrise));
+    assert_eq!(pos.next_sunset(sunrise), Some(sunset));
+    // Once today's has happened, it's tomorrow's
+    assert_eq!(pos.next_sunrise(sunrise), Some(pos.sunrise(next_day)));
+    assert_eq!(pos.next_sunset(sunset), Some(pos.sunset(next_day)));
+
+    // In the midnight sun season the next sunset is weeks away
+    let june = Utc.with_ymd_and_hms(2023, 6, 21, 12, 0, 0).unwrap();
+    let north = Pos::new(75., 20.);
+    let sunset = north.next_sunset(june).unwrap();
+    assert!(sunset - june > Duration::days(30), "{sunset}");
+    // ... and next to the pole, not until around the September equinox
+    let pole = Pos::new(89., 0.);
+    assert!(pole.next_sunset(june).unwrap() - june > Duration::days(80));
+}
+
+#[test]
+fn test_all_twilights() {
+    let pos = Pos::new(40., -75.);
+    let dt = Utc.with_ymd_and_hms(2023, 3, 20, 17, 0, 0).unwrap();
+    let set = pos.all_twilights(dt);
+    let close = |a: Option<DateTime<Utc>>, b: Option<DateTime<Utc>>| {
+        (a.unwrap() - b.unwrap()).num_seconds().abs() < 60
+    };
+    assert!(close(set.civil.dawn, pos.time_at_elevation(dt, -6., true)));
+    assert!(close(set.civil.dusk, pos.time_at_elevation(dt, -6., false)));
+    assert!(close(set.astronomical.dawn, pos.time_at_elevation(dt, -18., true)));
+    assert!(close(set.official.dusk, Some(pos.sunset(dt))));
+    assert!(set.astronomical.dawn < set.nautical.dawn);
+    assert!(set.nautical.dawn < set.civil.dawn);
+    assert!(set.civil.dawn < set.official.dawn);
+
+    // Midsummer in the Arctic: the sun never gets low enough for any twilight
+    let june = Utc.with_ymd_and_hms(2023, 6, 21, 12, 0, 0).unwrap();
+    let set = Pos::new(70., 20.).all_twilights(june);
+    assert_eq!(set.civil, Twilight { dawn: None, dusk: None });
+}
+
+/// The sun's daily events at a position, as returned by [`Pos::events`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SolarEvents {
+    pub sunrise: DateTime<Utc>,
+    pub solar_noon: DateTime<Utc>,
+    pub sunset: DateTime<Utc>,
+}
+
+impl SolarEvents {
+    pub fn day_length(&self) -> Duration {
+        self.sunset - self.sunrise
+    }
+}
+
+fn days_of_year(year: i32) -> impl Iterator<Item = NaiveDate> {
+    (1..=366).filter_map(move |ordinal| NaiveDate::from_yo_opt(year, ordinal))
+}
+
+/// Yields each date in `range` in order, or none if it ends before it starts
+pub fn days(range: RangeInclusive<NaiveDate>) -> impl FusedIterator<Item = NaiveDate> {
+    let (start, end) = range.into_inner();
+    std::iter::successors(Some(start), NaiveDate::succ_opt).take_while(move |date| *date <= end)
+}
+
+fn noon_utc(date: NaiveDate) -> DateTime<Utc> {
+    Utc.from_utc_datetime(&date.and_hms_opt(12, 0, 0).expect("Noon is a valid time"))
+}
+
+#[test]
+fn test_events() {
+    let dt = Utc.with_ymd_and_hms(2023, 5, 1, 12, 0, 0).unwrap();
+    let places = [
+        Pos::new(51.5, -0.13),
+        Pos::new(-33.9, 151.2),
+        Pos::new(64.1, -21.9),
+        Pos::new(69.65, 18.96),
+    ];
+    for pos in places {
+        let events = pos.events(dt);
+        assert_eq!(events.solar_noon, pos.solar_noon(dt));
+        assert_eq!(events.sunrise, pos.sunrise(dt), "{pos:?}");
+        assert_eq!(events.sunset, pos.sunset(dt), "{pos:?}");
+        assert_eq!(Ok(events.sunrise), pos.try_sunrise(dt), "{pos:?}");
+        assert_eq!(Ok(events.sunset), pos.try_sunset(dt), "{pos:?}");
+    }
+    let june = Utc.with_ymd_and_hms(2023, 6, 21, 12, 0, 0).unwrap();
+    assert_eq!(Pos::new(80., 0.).events(june).day_length(), Duration::hours(24));
+    assert_eq!(Pos::new(-80., 0.).events(june).day_length(), Duration::zero());
+}
+
+#[test]
+fn test_days() {
+    let start = NaiveDate::from_ymd_opt(2022, 2, 27).unwrap();
+    let mut iter = days(start..=NaiveDate::from_ymd_opt(2022, 3, 1).unwrap());
+    assert_eq!(iter.next(), Some(start));
+    assert_eq!(iter.next(), NaiveDate::from_ymd_opt(2022, 2, 28));
+    assert_eq!(iter.next(), NaiveDate::from_ymd_opt(2022, 3, 1));
+    for _ in 0..3 {
+        assert_eq!(iter.next(), None);
+    }
+    assert_eq!(days(start..=start).count(), 1);
+    assert_eq!(days(start..=start.pred_opt().unwrap()).count(), 0);
+    assert_eq!(days(NaiveDate::MAX..=NaiveDate::MAX).count(), 1);
+}
+
+#[test]
+fn test_days_across_year_and_leap_day() {
+    let date = |y, m, d| NaiveDate::from_ymd_opt(y, m, d).unwrap();
+    let new_year: Vec<_> = days(date(2023, 12, 30)..=date(2024, 1, 2)).collect();
+    assert_eq!(
+        new_year,
+        [date(2023, 12, 30), date(2023, 12, 31), date(2024, 1, 1), date(2024, 1, 2)]
+    );
+    let leap_day: Vec<_> = days(date(2024, 2, 28)..=date(2024, 3, 1)).collect();
+    assert_eq!(leap_day, [date(2024, 2, 28), date(2024, 2, 29), date(2024, 3, 1)]);
+    // Decades of days, each one day after the last
+    let decades: Vec<_> = days(date(1990, 1, 1)..=date(2039, 12, 31)).collect();
+    assert_eq!(decades.len(), 18_262);
+    assert!(decades.windows(2).all(|pair| pair[1] - pair[0] == Duration::days(1)));
+}
+
+#[test]
+fn test_gamma_leap_years() {
+    let at_noon = |y, m, d| gamma(Utc.with_ymd_and_hms(y, m, d, 12, 0, 0).unwrap()).0;
+    // The year wraps round to the start just as the calendar does, leap year or not
+    for year in [2023, 2024] {
+        let last = at_noon(year, 12, 31);
+        assert!(TAU - last < TAU / 364., "{year}: {last}");
+        assert!(TAU - last > TAU / 367., "{year}: {last}");
+        assert_eq!(at_noon(year + 1, 1, 1), 0.);
+    }
+    assert!((at_noon(2024, 3, 1) - 60. / 366. * TAU).abs() < 1e-12);
+    assert!((at_noon(2023, 3, 1) - 59. / 365. * TAU).abs() < 1e-12);
+}
+
+#[test]
+fn test_events_over() {
+    let pos = Pos::new(51.5, -0.13);
+    let start = NaiveDate::from_ymd_opt(2023, 6, 20).unwrap();
+    let end = NaiveDate::from_ymd_opt(2023, 6, 22).unwrap();
+    let events: Vec<_> = pos.events_over(start..=end).collect();
+    assert_eq!(events.len(), 3);
+    assert_eq!(events[0], (start, pos.events(noon_utc(start))));
+    assert_eq!(events[2].0, end);
+    // Lazy, so only the days taken are computed
+    let far = NaiveDate::from_ymd_opt(9999, 12, 31).unwrap();
+    assert_eq!(pos.events_over(start..=far).take(2).count(), 2);
+}
+
+#[test]
+fn test_days_where() {
+    let long_days = Pos::new(50., 0.).days_where(2023, |e| e.day_length() > Duration::hours(15));
+    assert!(long_days.contains(&NaiveDate::from_ymd_opt(2023, 6, 21).unwrap()));
+    assert!(long_days.iter().all(|date| (5..=8).contains(&date.month())));
+    assert!(Pos::new(0., 0.)
+        .days_where(2023, |e| e.day_length() > Duration::hours(15))
+        .is_empty());
+}
+
+#[test]
+fn test_annual_daylight_hours() {
+    for lat in [-40., 0., 20., 40., 50.] {
+        let hours = Pos::new(lat, 0.).annual_daylight_hours(2023);
+        assert!((4380. ..4500.).contains(&hours), "{lat}: {hours}");
+    }
+    // Polar days and nights don't cancel out exactly, but neither should produce nonsense
+    let hours = Pos::new(89., 0.).annual_daylight_hours(2023);
+    assert!((4380. ..4700.).contains(&hours), "{hours}");
+}
+
+/// A morning and an evening period of a day, either of which may not happen
+pub type DailyWindows = (Option<Range<DateTime<Utc>>>, Option<Range<DateTime<Utc>>>);
+
+/// Why an event doesn't happen on a given day
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SolarError {
+    #[error("The sun is above the horizon all day")]
+    SunAlwaysUp,
+    #[error("The sun is below the horizon all day")]
+    SunAlwaysDown,
+}
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum PosError {
+    #[error("Unable to parse '{0}' as a coordinate; expected \"lat, long\" or a geo: URI")]
+    Malformed(String),
+    #[error("Either latitude ({0}) or longitude ({1}) were out of range")]
+    OutOfRange(f64, f64),
+}
+
+/// Formats as `lat, long`, which parses back into the same position
+impl std::fmt::Display for Pos {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}, {}", self.lat, self.long)
+    }
+}
+
+/// Parses coordinates as copied from a map app: a bare `lat, long` pair, a `geo:lat,long` URI
+/// (any altitude or `;`/`?` parameters are ignored), or, with the `plus-codes` feature, a full
+/// Open Location Code such as `849VCWC8+R9`.
+impl FromStr for Pos {
+    type Err = PosError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let malformed = || PosError::Malformed(s.to_owned());
+        let trimmed = s.trim();
+        let (coords, max_parts) = match trimmed.get(..4) {
+            Some(scheme) if scheme.eq_ignore_ascii_case("geo:") => {
+                let coords = trimmed[4..].split([';', '?']).next().unwrap_or_default();
+                (coords, 3)
+            }
+            _ => (trimmed, 2),
+        };
+        let parts: Vec<_> = coords.split(',').map(str::trim).collect();
+        if parts.len() < 2 || parts.len() > max_parts {
+            #[cfg(feature = "plus-codes")]
+            let decoded = decode_plus_code(trimmed);
+            #[cfg(not(feature = "plus-codes"))]
+            let decoded = None;
+            return decoded.ok_or_else(malformed);
+        }
+        let lat: f64 = parts[0].parse().map_err(|_| malformed())?;
+        let long: f64 = parts[1].parse().map_err(|_| malformed())?;
+        Pos::try_new(lat, long)
+    }
+}
+
+/// Decodes a full (not shortened) Open Location Code to the centre of the area it describes
+#[cfg(feature = "plus-codes")]
+fn decode_plus_code(code: &str) -> Option<Pos> {
+    const ALPHABET: &str = "23456789CFGHJMPQRVWX";
+    if code.find('+') != Some(8) {
+        return None;
+    }
+    let digits: Vec<_> = code
+        .chars()
+        .filter(|ch| *ch != '+' && *ch != '0')
+        .map(|ch| ALPHABET.find(ch.to_ascii_uppercase()).map(|d| d as f64))
+        .collect::<Option<_>>()?;
+    if digits.len() < 2 || (digits.len() < 10 && digits.len() % 2 == 1) {
+        return None;
+    }
+    let (mut lat, mut long) = (-90., -180.);
+    let (mut lat_res, mut long_res) = (400., 400.);
+    for pair in digits[..digits.len().min(10)].chunks(2) {
+        lat_res /= 20.;
+        long_res /= 20.;
+        lat += pair[0] * lat_res;
+        long += pair[1] * long_res;
+    }
+    for digit in digits.iter().skip(10) {
+        lat_res /= 5.;
+        long_res /= 4.;
+        lat += (digit / 4.).floor() * lat_res;
+        long += (digit % 4.) * long_res;
+    }
+    Some(Pos::new(lat + lat_res / 2., long + long_res / 2.))
+}
+
+#[test]
+fn test_sun_up_at_local_midnight() {
+    let tromso = Pos::new(69.65, 18.96);
+    let summer = FixedOffset::east_opt(2 * 3600).unwrap();
+    let winter = FixedOffset::east_opt(3600).unwrap();
+    let june = Utc.with_ymd_and_hms(2023, 6, 21, 12, 0, 0).unwrap();
+    let december = Utc.with_ymd_and_hms(2023, 12, 21, 12, 0, 0).unwrap();
+    assert!(tromso.sun_up_at_local_midnight(june, &summer));
+    assert!(!tromso.sun_up_at_local_midnight(december, &winter));
+}
+
+#[test]
+fn test_equirectangular() {
+    assert_eq!(Pos::new(0., 0.).equirectangular(), (0.5, 0.5));
+    assert_eq!(Pos::new(0., -90.).equirectangular(), (0.25, 0.5));
+    assert_eq!(Pos::new(90., 180.).equirectangular(), (1., 0.));
+    assert_eq!(Pos::new(-90., 180.).equirectangular(), (1., 1.));
+    // 180° west is the same meridian, so it's on the right-hand edge too
+    assert_eq!(Pos::new(90., -180.).equirectangular(), (1., 0.));
+}
+
+#[test]
+fn test_parse_pos() {
+    let pos: Pos = "40.7128, -74.0060".parse().unwrap();
+    assert_eq!((pos.lat, pos.long), (40.7128, -74.006));
+    let pos: Pos = "geo:37.786971,-122.399677;u=35".parse().unwrap();
+    assert_eq!((pos.lat, pos.long), (37.786971, -122.399677));
+    let pos: Pos = "GEO:-33.8688,151.2093,12".parse().unwrap();
+    assert_eq!((pos.lat, pos.long), (-33.8688, 151.2093));
+
+    assert!(matches!("Paris, France".parse::<Pos>(), Err(PosError::Malformed(_))));
+    assert!(matches!("1,2,3".parse::<Pos>(), Err(PosError::Malformed(_))));
+    assert_eq!("-122.6, 45.5".parse::<Pos>().unwrap_err(), PosError::OutOfRange(-122.6, 45.5));
+}
+
+#[cfg(feature = "plus-codes")]
+#[test]
+fn test_parse_plus_code() {
+    let pos: Pos = "849VCWC8+R9".parse().unwrap();
+    assert!((pos.lat - 37.4220625).abs() < 1e-6);
+    assert!((pos.long - -122.0840625).abs() < 1e-6);
+    assert!("849VCWC8R9".parse::<Pos>().is_err());
+}
+
+/// Conditions under which the NOAA approximations used here may be off by more than a minute
+#[derive(Error, Debug, Clone, Copy, PartialEq)]
+pub enum AccuracyWarning {
+    #[error("Latitude {0}° is far from the equator; the sun meets the horizon at a shallow angle there, so small errors become minutes")]
+    HighLatitude(f64),
+    #[error("The year {0} is far from the present epoch the approximations were fitted to")]
+    DistantYear(i32),
+}
+
+/// Returns how far apparent (sundial) solar time runs ahead of mean (clock) solar time on Earth
+/// at the given instant. This swings between about -14 and +16 minutes over the year.
+pub fn equation_of_time(dt: DateTime<Utc>) -> Duration {
+  let minutes = eqtime(gamma(dt));
+  Duration::milliseconds((minutes * 60_000.).round() as i64)
+}
+
+/// Approximate atmospheric refraction in degrees for a body at `elevation` degrees, following
+/// NOAA's solar calculator
+fn refraction(elevation: f64) -> f64 {
+  let tan = elevation.to_radians().tan();
+  let arcseconds = if elevation > 85. {
+    0.
+  } else if elevation > 5. {
+    58.1 / tan - 0.07 / tan.powi(3) + 0.000_086 / tan.powi(5)
+  } else if elevation > -0.575 {
+    1735. + elevation * (-518.2 + elevation * (103.4 + elevation * (-12.79 + elevation * 0.711)))
+  } else {
+    -20.772 / tan
+  };
+  arcseconds / 3600.
+}
+
+/// Returns sunrise (as `dawn`) and sunset (as `dusk`) on Earth at each of `lats` along the meridian
+/// at `long`, on the day of `dt`. The declination and equation of time don't depend on latitude,
+/// so they're computed once at the meridian's solar noon and shared by every latitude. Latitudes
+/// in polar day or night have no sunrise or sunset.
+pub fn meridian_sunrise_sunset(long: f64, dt: DateTime<Utc>, lats: &[f64]) -> Vec<Twilight> {
+  let noon = Pos::new(0., long).solar_noon(dt);
+  let params = Planet::EARTH.params(noon);
+  let day_start = Planet::EARTH.day_start(dt);
+  lats
+    .iter()
+    .map(|&lat| {
+      let pos = Pos::new(lat, long);
+      Twilight {
+        dawn: pos.event_with_params(day_start, params, pos.horizon_zenith(), true).ok(),
+        dusk: pos.event_with_params(day_start, params, pos.horizon_zenith(), false).ok(),
+      }
+    })
+    .collect()
+}
+
+/// Julian centuries from J2000.0 (noon UTC on 1 January 2000) to `dt`, ignoring the minute or
+/// so between UTC and terrestrial time
+fn julian_centuries(dt: DateTime<Utc>) -> f64 {
+  const J2000: i64 = 946_728_000;
+  (dt.timestamp() - J2000) as f64 / 86_400. / 36_525.
+}
+
+/// The sun's apparent ecliptic longitude and the obliquity of the ecliptic at `dt`, both in
+/// degrees, from Meeus' low-precision solar theory. Good to about 0.01°, or a quarter of an hour
+/// of the sun's motion along the ecliptic.
+fn apparent_longitude_and_obliquity(dt: DateTime<Utc>) -> (f64, f64) {
+  let t = julian_centuries(dt);
+  let mean_longitude = 280.466_46 + t * (36_000.769_83 + t * 0.000_303_2);
+  let anomaly = (357.529_11 + t * (35_999.050_29 - t * 0.000_153_7)).to_radians();
+  let centre = (1.914_602 - t * (0.004_817 + t * 0.000_014)) * anomaly.sin()
+    + (0.019_993 - t * 0.000_101) * (2. * anomaly).sin()
+    + 0.000_289 * (3. * anomaly).sin();
+  let node = (125.04 - 1_934.136 * t).to_radians();
+  let longitude = mean_longitude + centre - 0.005_69 - 0.004_78 * node.sin();
+  let obliquity = 23.439_291 - t * 0.013_004_2 + 0.002_56 * node.cos();
+  (longitude.rem_euclid(360.), obliquity)
+}
+
+/// Returns the sun's declination on Earth at the given instant, in degrees north of the equator.
+/// This comes from the sun's ecliptic longitude rather than NOAA's fitted series, which is off
+/// by a few tenths of a degree around the equinoxes.
+pub fn solar_declination(dt: DateTime<Utc>) -> f64 {
+  let (longitude, obliquity) = apparent_longitude_and_obliquity(dt);
+  (obliquity.to_radians().sin() * longitude.to_radians().sin()).asin().to_degrees()
+}
+
+/// Returns the point on Earth where the sun is directly overhead at the given instant. Its
+/// latitude is the sun's declination; its longitude is wherever it's apparent solar noon.
+pub fn subsolar_point(dt: DateTime<Utc>) -> Pos {
+  let eqtime = eqtime(gamma(dt));
+  let ut_hours = dt.num_seconds_from_midnight() as f64 / 3600.;
+  let long = -15. * (ut_hours - 12. + eqtime / 60.);
+  Pos::new(solar_declination(dt), (long + 180.).rem_euclid(360.) - 180.)
+}
+
+#[test]
+fn test_subsolar_point() {
+    let dt = Utc.with_ymd_and_hms(2023, 6, 21, 12, 0, 0).unwrap();
+    let point = subsolar_point(dt);
+    assert!((point.lat() - 23.44).abs() < 0.1, "{point:?}");
+    assert!(point.long().abs() < 1., "{point:?}");
+    assert!(point.solar_position(dt).0 > 89.5);
+
+    // Half a day later it's on the far side of the world, within ±180°
+    let point = subsolar_point(dt + Duration::hours(12));
+    assert!(point.long() > -180. && point.long() <= 180.);
+    assert!(point.long().abs() > 179., "{point:?}");
+    let dt = Utc.with_ymd_and_hms(2023, 3, 20, 18, 0, 0).unwrap();
+    assert!((subsolar_point(dt).long() + 90.).abs() < 3.);
+}
+
+/// The instants the seasons turn in a year. The sun crosses the equator heading north at the
+/// March equinox and heading south at the September one, and is furthest north at the June
+/// solstice and furthest south at the December one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Seasons {
+    pub march_equinox: DateTime<Utc>,
+    pub june_solstice: DateTime<Utc>,
+    pub september_equinox: DateTime<Utc>,
+    pub december_solstice: DateTime<Utc>,
+}
+
+/// Finds the equinoxes and solstices of the given (UTC) year to within a few minutes: the
+/// instants the sun's apparent ecliptic longitude reaches 0°, 90°, 180° and 270°. Each is found
+/// by scanning a day at a time and then bisecting the day it falls in. Returns `None` if the
+/// year is out of range.
+pub fn seasons(year: i32) -> Option<Seasons> {
+  let midnight = |date: NaiveDate| date.and_hms_opt(0, 0, 0).expect("Midnight is a valid time");
+  let days: Vec<_> = days_of_year(year)
+    .map(|date| Utc.from_utc_datetime(&midnight(date)))
+    .collect();
+  if days.is_empty() {
+    return None;
+  }
+  // Signed angle the sun still has to travel to reach `target`, in (-180, 180]
+  let before = |dt: DateTime<Utc>, target: f64| {
+    (apparent_longitude_and_obliquity(dt).0 - target + 180.).rem_euclid(360.) - 180. < 0.
+  };
+  let reaching = |target: f64| {
+    let i = (1..days.len())
+      .find(|&i| before(days[i - 1], target) && !before(days[i], target))
+      .expect("The sun passes every longitude once a year");
+    bisect(days[i - 1], days[i], |dt| before(dt, target))
+  };
+  Some(Seasons {
+    march_equinox: reaching(0.),
+    june_solstice: reaching(90.),
+    september_equinox: reaching(180.),
+    december_solstice: reaching(270.),
+  })
+}
+
+/// Narrows `from..to` down to the minute at which `before` stops holding, given that it holds
+/// at `from` and doesn't at `to`
+fn bisect(
+  mut from: DateTime<Utc>,
+  mut to: DateTime<Utc>,
+  before: impl Fn(DateTime<Utc>) -> bool,
+) -> DateTime<Utc> {
+  while to - from > Duration::minutes(1) {
+    let mid = from + (to - from) / 2;
+    if before(mid) {
+      from = mid;
+    } else {
+      to = mid;
+    }
+  }
+  to
+}
+
+#[test]
+fn test_seasons() {
+    let seasons = seasons(2023).unwrap();
+    // Published instants for 2023, which the low-precision solar longitude finds to within a
+    // few minutes
+    let near = |found: DateTime<Utc>, (month, day, hour, min)| {
+        let expected = Utc.with_ymd_and_hms(2023, month, day, hour, min, 0).unwrap();
+        (found - expected).num_minutes().abs() < 15
+    };
+    assert!(near(seasons.march_equinox, (3, 20, 21, 24)), "{seasons:?}");
+    assert!(near(seasons.june_solstice, (6, 21, 14, 57)), "{seasons:?}");
+    assert!(near(seasons.september_equinox, (9, 23, 6, 50)), "{seasons:?}");
+    assert!(near(seasons.december_solstice, (12, 22, 3, 27)), "{seasons:?}");
+    assert!(solar_declination(seasons.march_equinox).abs() < 0.01);
+    assert!(solar_declination(seasons.june_solstice) > 23.4);
+}
+